@@ -0,0 +1,61 @@
+use near_sdk::serde::Serialize;
+use near_sdk::{log, serde_json};
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct EventLog<'a, T: Serialize> {
+    standard: &'a str,
+    version: &'a str,
+    #[serde(flatten)]
+    event: &'a T,
+}
+
+/// logs an event in the NEP-297 format:
+///     EVENT_JSON:{"standard": "...", "version": "...", "event": "...", "data": {...}}
+///
+/// `event` has to serialize into the "event" and "data" fields,
+/// i.e. be an enum with #[serde(tag = "event", content = "data")]
+pub fn emit_event<T: Serialize>(standard: &str, version: &str, event: &T) {
+    let event_log = EventLog {
+        standard,
+        version,
+        event,
+    };
+    log!("EVENT_JSON:{}", serde_json::to_string(&event_log).unwrap());
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::json_types::U128;
+    use near_sdk::test_utils::{get_logs, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    #[derive(Serialize)]
+    #[serde(
+        crate = "near_sdk::serde",
+        tag = "event",
+        content = "data",
+        rename_all = "snake_case"
+    )]
+    enum TestEvent {
+        ItemCreate { item_id: String, amount: U128 },
+    }
+
+    #[test]
+    fn test_emit_event() {
+        testing_env!(VMContextBuilder::new().build());
+        let event = TestEvent::ItemCreate {
+            item_id: "i1".to_string(),
+            amount: U128(100),
+        };
+        emit_event("test", "1.0.0", &event);
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"test","version":"1.0.0","event":"item_create","data":{"item_id":"i1","amount":"100"}}"#
+            ]
+        );
+    }
+}
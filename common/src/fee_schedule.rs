@@ -0,0 +1,138 @@
+use crate::fees::HUNDRED_PERCENT_BPS;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::LookupMap;
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{require, AccountId, Balance, IntoStorageKey};
+
+/// the fee of the accounts whose cumulative volume has reached `min_volume`;
+/// `min_volume` is a string in JSON, as a JSON number can't hold it
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeTier {
+    pub min_volume: U128,
+    pub fee_bps: u128,
+}
+
+/// picks the fee of an account: its override, if any,
+/// otherwise the one of the highest tier its volume has reached, otherwise the base fee
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct FeeSchedule {
+    tiers: Vec<FeeTier>,
+    overrides: LookupMap<AccountId, u128>,
+    volumes: LookupMap<AccountId, Balance>,
+}
+
+impl FeeSchedule {
+    pub fn new<S: IntoStorageKey>(overrides_prefix: S, volumes_prefix: S) -> Self {
+        Self {
+            tiers: Vec::new(),
+            overrides: LookupMap::new(overrides_prefix),
+            volumes: LookupMap::new(volumes_prefix),
+        }
+    }
+
+    pub fn fee_bps_of(&self, account_id: &AccountId, base_fee_bps: u128) -> u128 {
+        if let Some(fee_bps) = self.overrides.get(account_id) {
+            return fee_bps;
+        }
+
+        let volume = self.volume_of(account_id);
+        self.tiers
+            .iter()
+            .rev()
+            .find(|t| t.min_volume.0 <= volume)
+            .map_or(base_fee_bps, |t| t.fee_bps)
+    }
+
+    pub fn tiers(&self) -> &[FeeTier] {
+        &self.tiers
+    }
+
+    /// replaces the tiers; they have to be sorted by `min_volume`, ascending
+    pub fn set_tiers(&mut self, tiers: Vec<FeeTier>) {
+        for (i, t) in tiers.iter().enumerate() {
+            require_fee_bps(t.fee_bps);
+            require!(
+                i == 0 || tiers[i - 1].min_volume.0 < t.min_volume.0,
+                "the tiers must be sorted by min_volume and not repeat it"
+            );
+        }
+
+        self.tiers = tiers;
+    }
+
+    pub fn override_of(&self, account_id: &AccountId) -> Option<u128> {
+        self.overrides.get(account_id)
+    }
+
+    /// sets the fee of an account regardless of its volume; None removes the override
+    pub fn set_override(&mut self, account_id: &AccountId, fee_bps: Option<u128>) {
+        match fee_bps {
+            Some(fee_bps) => {
+                require_fee_bps(fee_bps);
+                self.overrides.insert(account_id, &fee_bps);
+            }
+            None => {
+                self.overrides.remove(account_id);
+            }
+        }
+    }
+
+    pub fn volume_of(&self, account_id: &AccountId) -> Balance {
+        self.volumes.get(account_id).unwrap_or(0)
+    }
+
+    pub fn add_volume(&mut self, account_id: &AccountId, amount: Balance) {
+        if amount == 0 {
+            return;
+        }
+
+        let volume = self.volume_of(account_id);
+        self.volumes.insert(account_id, &(volume + amount));
+    }
+}
+
+fn require_fee_bps(fee_bps: u128) {
+    require!(
+        fee_bps <= HUNDRED_PERCENT_BPS,
+        format!(
+            "fee ({}) must be equal to or less than {}",
+            fee_bps, HUNDRED_PERCENT_BPS
+        )
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::accounts;
+
+    #[test]
+    fn test_fee_bps_of() {
+        let mut schedule = FeeSchedule::new(b"o", b"v");
+        schedule.set_tiers(vec![
+            FeeTier {
+                min_volume: U128(1_000),
+                fee_bps: 75,
+            },
+            FeeTier {
+                min_volume: U128(10_000),
+                fee_bps: 50,
+            },
+        ]);
+        assert_eq!(schedule.fee_bps_of(&accounts(0), 100), 100);
+
+        schedule.add_volume(&accounts(0), 1_000);
+        assert_eq!(schedule.fee_bps_of(&accounts(0), 100), 75);
+
+        schedule.add_volume(&accounts(0), 9_000);
+        assert_eq!(schedule.fee_bps_of(&accounts(0), 100), 50);
+
+        schedule.set_override(&accounts(0), Some(0));
+        assert_eq!(schedule.fee_bps_of(&accounts(0), 100), 0);
+
+        schedule.set_override(&accounts(0), None);
+        assert_eq!(schedule.fee_bps_of(&accounts(0), 100), 50);
+    }
+}
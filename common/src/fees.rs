@@ -0,0 +1,160 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::{require, Balance};
+
+/// 100% in basis points; 1 bps is 0.01%, hence 75 bps is 0.75%
+pub const HUNDRED_PERCENT_BPS: u128 = 10_000;
+
+/// 1% in basis points; the fees of the layouts deployed first were in percent
+pub const BPS_PER_PERCENT: u128 = HUNDRED_PERCENT_BPS / 100;
+
+/// how the fractional part of a fee gets rounded
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Rounding {
+    /// in favour of the payer
+    Down,
+    /// in favour of the fee receiver
+    Up,
+    /// to the nearest one, half up
+    HalfUp,
+}
+
+/// a gross amount split into what the receiver gets and the fee;
+/// `net + fee` always equals the gross amount
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct FeeSplit {
+    pub net: Balance,
+    pub fee: Balance,
+}
+
+/// returns `amount * bps / HUNDRED_PERCENT_BPS`, rounded according to `rounding`
+///
+/// the amount is split into the whole units of HUNDRED_PERCENT_BPS and the remainder
+/// so that the multiplication comes before the division and yet can't overflow
+pub fn apply_bps(amount: Balance, bps: u128, rounding: Rounding) -> Balance {
+    require!(
+        bps <= HUNDRED_PERCENT_BPS,
+        format!(
+            "basis points ({}) must be equal to or less than {}",
+            bps, HUNDRED_PERCENT_BPS
+        )
+    );
+
+    let whole = amount / HUNDRED_PERCENT_BPS * bps;
+    let part = amount % HUNDRED_PERCENT_BPS * bps;
+    let (quotient, remainder) = (part / HUNDRED_PERCENT_BPS, part % HUNDRED_PERCENT_BPS);
+    let round_up = match rounding {
+        Rounding::Down => false,
+        Rounding::Up => remainder > 0,
+        Rounding::HalfUp => remainder * 2 >= HUNDRED_PERCENT_BPS,
+    };
+
+    whole + quotient + u128::from(round_up)
+}
+
+/// splits `gross` into the fee of `fee_bps` and the rest
+pub fn split(gross: Balance, fee_bps: u128, rounding: Rounding) -> FeeSplit {
+    let fee = apply_bps(gross, fee_bps, rounding);
+    FeeSplit { net: gross - fee, fee }
+}
+
+/// the absolute bounds of a fee, set by the owner of a contract;
+/// there's no upper bound if `max_fee` is None
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy, Default, PartialEq, Debug)]
+pub struct FeeLimits {
+    pub min_fee: Balance,
+    pub max_fee: Option<Balance>,
+}
+
+impl FeeLimits {
+    pub fn new(min_fee: Balance, max_fee: Option<Balance>) -> Self {
+        if let Some(max_fee) = max_fee {
+            require!(
+                min_fee <= max_fee,
+                format!(
+                    "min_fee ({}) must be equal to or less than max_fee ({})",
+                    min_fee, max_fee
+                )
+            );
+        }
+
+        Self { min_fee, max_fee }
+    }
+
+    /// brings `fee` within the bounds; it never exceeds `gross`, though
+    pub fn clamp(&self, fee: Balance, gross: Balance) -> Balance {
+        let fee = fee.max(self.min_fee);
+        let fee = self.max_fee.map_or(fee, |max_fee| fee.min(max_fee));
+        fee.min(gross)
+    }
+}
+
+/// splits `gross` into the fee of `fee_bps`, clamped by `limits`, and the rest;
+/// a fee of 0 bps, such as of an exempt account, doesn't get clamped
+pub fn split_clamped(gross: Balance, fee_bps: u128, limits: &FeeLimits, rounding: Rounding) -> FeeSplit {
+    if fee_bps == 0 {
+        return split(gross, fee_bps, rounding);
+    }
+
+    let fee = limits.clamp(apply_bps(gross, fee_bps, rounding), gross);
+    FeeSplit { net: gross - fee, fee }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_adds_up() {
+        for gross in [0, 1, 99, 101, 9_999, 10_001, 123_456_789, u128::MAX] {
+            for fee_bps in [0, 1, 75, 1_000, 9_999, 10_000] {
+                for rounding in [Rounding::Down, Rounding::Up, Rounding::HalfUp] {
+                    let s = split(gross, fee_bps, rounding);
+                    assert_eq!(s.net + s.fee, gross);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_split_rounding() {
+        //0.75% of 1000 is 7.5
+        assert_eq!(split(1000, 75, Rounding::Down), FeeSplit { net: 993, fee: 7 });
+        assert_eq!(split(1000, 75, Rounding::Up), FeeSplit { net: 992, fee: 8 });
+        assert_eq!(split(1000, 75, Rounding::HalfUp), FeeSplit { net: 992, fee: 8 });
+
+        //10% of 99 is 9.9, not the whole amount
+        assert_eq!(split(99, 1_000, Rounding::Down), FeeSplit { net: 90, fee: 9 });
+        assert_eq!(split(10_001, 1, Rounding::HalfUp), FeeSplit { net: 10_000, fee: 1 });
+    }
+
+    #[test]
+    fn test_split_clamped() {
+        let limits = FeeLimits::new(5, Some(50));
+        assert_eq!(
+            split_clamped(100, 100, &limits, Rounding::Down),
+            FeeSplit { net: 95, fee: 5 }
+        );
+        assert_eq!(
+            split_clamped(1000, 100, &limits, Rounding::Down),
+            FeeSplit { net: 990, fee: 10 }
+        );
+        assert_eq!(
+            split_clamped(10_000, 100, &limits, Rounding::Down),
+            FeeSplit { net: 9_950, fee: 50 }
+        );
+
+        //the fee never exceeds the gross amount
+        assert_eq!(
+            split_clamped(3, 100, &limits, Rounding::Down),
+            FeeSplit { net: 0, fee: 3 }
+        );
+        assert_eq!(
+            split_clamped(100, 0, &limits, Rounding::Down),
+            FeeSplit { net: 100, fee: 0 }
+        );
+        assert_eq!(
+            split_clamped(1000, 100, &FeeLimits::default(), Rounding::Down),
+            split(1000, 100, Rounding::Down)
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::{LookupMap, TreeMap, UnorderedSet};
+use near_sdk::{env, require};
+
+/// the ids of the items by a key, such as an account or a status
+pub type Index<K, I> = LookupMap<K, UnorderedSet<I>>;
+
+/// adds `id` to the set of `key`; `prefix` is that of the sets of the index
+pub fn index_add<K, I>(index: &mut Index<K, I>, prefix: &[u8], key: &K, id: &I)
+where
+    K: BorshSerialize,
+    I: BorshSerialize + BorshDeserialize,
+{
+    let mut ids = index.get(key).unwrap_or_else(|| {
+        //each set needs a prefix of its own
+        let key_hash = env::sha256(&key.try_to_vec().unwrap());
+        UnorderedSet::new([prefix, &key_hash].concat())
+    });
+    ids.insert(id);
+    index.insert(key, &ids);
+}
+
+/// removes `id` from the set of `key`, along with the set once it's empty
+pub fn index_remove<K, I>(index: &mut Index<K, I>, key: &K, id: &I)
+where
+    K: BorshSerialize,
+    I: BorshSerialize + BorshDeserialize,
+{
+    if let Some(mut ids) = index.get(key) {
+        ids.remove(id);
+        if ids.is_empty() {
+            index.remove(key);
+        } else {
+            index.insert(key, &ids);
+        }
+    }
+}
+
+/// a page of the ids of `key`
+pub fn index_page<K, I>(index: &Index<K, I>, key: &K, from_index: u64, limit: u64) -> Vec<I>
+where
+    K: BorshSerialize,
+    I: BorshSerialize + BorshDeserialize,
+{
+    match index.get(key) {
+        Some(ids) => ids.iter().skip(from_index as usize).take(limit as usize).collect(),
+        None => Vec::new(),
+    }
+}
+
+/// the progress of indexing, page by page, the items stored before the indexes existed;
+/// `migrate` only starts it, as indexing all of them at once could take more gas than a call has
+#[derive(BorshDeserialize, BorshSerialize, Debug, PartialEq)]
+pub enum Backfill<K> {
+    /// the items after `after`, or all of them if none, are yet to be indexed
+    Pending {
+        after: Option<K>,
+    },
+    Done,
+}
+
+impl<K> Backfill<K>
+where
+    K: Ord + Clone + BorshSerialize + BorshDeserialize,
+{
+    /// the keys of up to `limit` items to index next, moving past them; Done once there are none left after them
+    pub fn next_page<V>(&mut self, items: &TreeMap<K, V>, limit: u64) -> Vec<K>
+    where
+        V: BorshSerialize + BorshDeserialize,
+    {
+        require!(limit > 0, "limit must be greater than 0");
+        let keys: Vec<K> = match self {
+            Self::Pending { after: Some(after) } => items
+                .iter_from(after.clone())
+                .map(|(k, _)| k)
+                .take(limit as usize)
+                .collect(),
+            Self::Pending { after: None } => items.iter().map(|(k, _)| k).take(limit as usize).collect(),
+            Self::Done => Vec::new(),
+        };
+
+        //a page short of `limit` is the last one
+        *self = match keys.last() {
+            Some(last) if keys.len() as u64 == limit => Self::Pending {
+                after: Some(last.clone()),
+            },
+            _ => Self::Done,
+        };
+        keys
+    }
+
+    pub fn is_done(&self) -> bool {
+        matches!(self, Self::Done)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    #[test]
+    fn test_add_and_remove() {
+        testing_env!(VMContextBuilder::new().build());
+        let mut index: Index<String, u64> = LookupMap::new(b"x");
+        let key = "ref".to_string();
+        index_add(&mut index, b"x", &key, &1);
+        index_add(&mut index, b"x", &key, &2);
+        index_add(&mut index, b"x", &"other".to_string(), &3);
+        assert_eq!(index_page(&index, &key, 0, 10), vec![1, 2]);
+        assert_eq!(index_page(&index, &key, 1, 10), vec![2]);
+
+        index_remove(&mut index, &key, &1);
+        index_remove(&mut index, &key, &2);
+        assert!(index.get(&key).is_none());
+        assert_eq!(index_page(&index, &"other".to_string(), 0, 10), vec![3]);
+    }
+
+    #[test]
+    fn test_backfill_in_pages() {
+        testing_env!(VMContextBuilder::new().build());
+        let mut items: TreeMap<String, u64> = TreeMap::new(b"t");
+        for id in ["a", "b", "c"] {
+            items.insert(&id.to_string(), &1);
+        }
+
+        let mut backfill = Backfill::Pending { after: None };
+        assert_eq!(backfill.next_page(&items, 2), vec!["a".to_string(), "b".to_string()]);
+        assert!(!backfill.is_done());
+        assert_eq!(backfill.next_page(&items, 2), vec!["c".to_string()]);
+        assert!(backfill.is_done());
+        assert!(backfill.next_page(&items, 2).is_empty());
+    }
+}
@@ -0,0 +1,93 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::LookupMap;
+use near_sdk::{require, AccountId, Balance, IntoStorageKey};
+
+/// NEP-141 token contract; None for NEAR
+pub type TokenId = Option<AccountId>;
+
+/// the funds each account may withdraw from a contract;
+/// payouts get credited here, instead of being transferred right away
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ClaimLedger {
+    balances: LookupMap<(AccountId, TokenId), Balance>,
+}
+
+impl ClaimLedger {
+    pub fn new<S: IntoStorageKey>(prefix: S) -> Self {
+        Self {
+            balances: LookupMap::new(prefix),
+        }
+    }
+
+    pub fn balance_of(&self, account_id: &AccountId, token_id: &TokenId) -> Balance {
+        self.balances.get(&(account_id.clone(), token_id.clone())).unwrap_or(0)
+    }
+
+    pub fn credit(&mut self, account_id: &AccountId, token_id: &TokenId, amount: Balance) {
+        if amount == 0 {
+            return;
+        }
+
+        let balance = self.balance_of(account_id, token_id);
+        self.balances
+            .insert(&(account_id.clone(), token_id.clone()), &(balance + amount));
+    }
+
+    /// takes `amount` off the balance of an account; all of it, if `amount` isn't provided
+    /// returns the amount taken
+    pub fn debit(&mut self, account_id: &AccountId, token_id: &TokenId, amount: Option<Balance>) -> Balance {
+        let balance = self.balance_of(account_id, token_id);
+        let amount = amount.unwrap_or(balance);
+        require!(
+            amount <= balance,
+            format!(
+                "amount ({}) must be equal to or less than the claimable balance ({})",
+                amount, balance
+            )
+        );
+        require!(amount > 0, "there's nothing to withdraw");
+
+        let key = (account_id.clone(), token_id.clone());
+        if amount == balance {
+            self.balances.remove(&key);
+        } else {
+            self.balances.insert(&key, &(balance - amount));
+        }
+
+        amount
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    #[test]
+    fn test_credit_and_debit() {
+        testing_env!(VMContextBuilder::new().build());
+        let mut ledger = ClaimLedger::new(b"c");
+        let token: TokenId = Some("token.near".parse().unwrap());
+
+        ledger.credit(&accounts(0), &None, 100);
+        ledger.credit(&accounts(0), &None, 50);
+        ledger.credit(&accounts(0), &token, 10);
+        assert_eq!(ledger.balance_of(&accounts(0), &None), 150);
+        assert_eq!(ledger.balance_of(&accounts(0), &token), 10);
+
+        assert_eq!(ledger.debit(&accounts(0), &None, Some(100)), 100);
+        assert_eq!(ledger.debit(&accounts(0), &None, None), 50);
+        assert_eq!(ledger.balance_of(&accounts(0), &None), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "must be equal to or less than the claimable balance")]
+    fn test_debit_more_than_balance() {
+        testing_env!(VMContextBuilder::new().build());
+        let mut ledger = ClaimLedger::new(b"c");
+        ledger.credit(&accounts(0), &None, 100);
+        ledger.debit(&accounts(0), &None, Some(101));
+    }
+}
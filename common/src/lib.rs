@@ -0,0 +1,13 @@
+//! code shared by the escrow, lottery and donation contracts
+
+pub mod events;
+pub mod fee_schedule;
+pub mod fees;
+pub mod index;
+pub mod ledger;
+pub mod pause;
+pub mod roles;
+pub mod storage;
+pub mod treasury;
+pub mod upgrade;
+pub mod versioned;
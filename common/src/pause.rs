@@ -0,0 +1,92 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::require;
+use near_sdk::serde::{Deserialize, Serialize};
+
+/// a part of a contract that may be paused on its own
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+pub enum Feature {
+    NewDeals,
+    Payouts,
+    Donations,
+}
+
+impl Feature {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Feature::NewDeals => "new_deals",
+            Feature::Payouts => "payouts",
+            Feature::Donations => "donations",
+        }
+    }
+}
+
+/// what has been paused; pausing the whole contract pauses every feature as well
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct PauseFlags {
+    contract: bool,
+    features: Vec<Feature>,
+}
+
+impl PauseFlags {
+    /// whether `feature` or, if it's None, the whole contract is paused
+    pub fn is_paused(&self, feature: Option<Feature>) -> bool {
+        match feature {
+            Some(f) => self.contract || self.features.contains(&f),
+            None => self.contract,
+        }
+    }
+
+    pub fn paused_features(&self) -> Vec<Feature> {
+        self.features.clone()
+    }
+
+    /// pauses or unpauses `feature` or, if it's None, the whole contract;
+    /// returns false if nothing has changed
+    pub fn set(&mut self, feature: Option<Feature>, paused: bool) -> bool {
+        let was_paused = match feature {
+            Some(f) => self.features.contains(&f),
+            None => self.contract,
+        };
+        if was_paused == paused {
+            return false;
+        }
+
+        match (feature, paused) {
+            (Some(f), true) => self.features.push(f),
+            (Some(f), false) => self.features.retain(|x| *x != f),
+            (None, _) => self.contract = paused,
+        }
+
+        true
+    }
+
+    pub fn require_not_paused(&self, feature: Option<Feature>) {
+        require!(!self.contract, "the contract is paused");
+        if let Some(f) = feature {
+            require!(!self.features.contains(&f), format!("{} are paused", f.as_str()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pause_and_unpause() {
+        let mut flags = PauseFlags::default();
+        assert!(flags.set(Some(Feature::Payouts), true));
+        assert!(!flags.set(Some(Feature::Payouts), true));
+        assert!(flags.is_paused(Some(Feature::Payouts)));
+        assert!(!flags.is_paused(Some(Feature::NewDeals)));
+        assert!(!flags.is_paused(None));
+
+        assert!(flags.set(None, true));
+        assert!(flags.is_paused(Some(Feature::NewDeals)));
+
+        assert!(flags.set(None, false));
+        assert!(flags.set(Some(Feature::Payouts), false));
+        assert!(!flags.is_paused(Some(Feature::Payouts)));
+    }
+}
@@ -0,0 +1,113 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::LookupSet;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{require, AccountId, IntoStorageKey};
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+pub enum Role {
+    // has every role and manages the roles of others
+    SuperAdmin,
+    // sets the fees
+    FeeManager,
+    // resolves disputes, removes items
+    Operator,
+    // pauses and unpauses
+    Pauser,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::SuperAdmin => "super_admin",
+            Role::FeeManager => "fee_manager",
+            Role::Operator => "operator",
+            Role::Pauser => "pauser",
+        }
+    }
+}
+
+/// the roles granted to the accounts besides the owner, who has every role implicitly
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Roles {
+    members: LookupSet<(AccountId, Role)>,
+}
+
+impl Roles {
+    pub fn new<S: IntoStorageKey>(prefix: S) -> Self {
+        Self {
+            members: LookupSet::new(prefix),
+        }
+    }
+
+    pub fn has_role(&self, owner_id: &AccountId, account_id: &AccountId, role: Role) -> bool {
+        account_id == owner_id
+            || self.members.contains(&(account_id.clone(), Role::SuperAdmin))
+            || self.members.contains(&(account_id.clone(), role))
+    }
+
+    pub fn require_role(&self, owner_id: &AccountId, account_id: &AccountId, role: Role) {
+        require!(
+            self.has_role(owner_id, account_id, role),
+            format!("only owner or {} may call this method", role.as_str())
+        );
+    }
+
+    /// grants `role` to `account_id` on behalf of `caller`;
+    /// super admins grant roles, but only the owner grants the super admin one;
+    /// returns false if the account has had it already
+    pub fn grant(&mut self, owner_id: &AccountId, caller: &AccountId, account_id: &AccountId, role: Role) -> bool {
+        self.require_manager(owner_id, caller, role);
+        self.members.insert(&(account_id.clone(), role))
+    }
+
+    /// revokes `role` from `account_id` on behalf of `caller`, the same way as `grant`;
+    /// returns false if the account hasn't had it
+    pub fn revoke(&mut self, owner_id: &AccountId, caller: &AccountId, account_id: &AccountId, role: Role) -> bool {
+        self.require_manager(owner_id, caller, role);
+        self.members.remove(&(account_id.clone(), role))
+    }
+
+    fn require_manager(&self, owner_id: &AccountId, caller: &AccountId, role: Role) {
+        match role {
+            Role::SuperAdmin => require!(caller == owner_id, "only owner may manage super admins"),
+            _ => self.require_role(owner_id, caller, Role::SuperAdmin),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    #[test]
+    fn test_grant_and_revoke() {
+        testing_env!(VMContextBuilder::new().build());
+        let owner = accounts(0);
+        let mut roles = Roles::new(b"r");
+        assert!(roles.has_role(&owner, &owner, Role::Pauser));
+        assert!(!roles.has_role(&owner, &accounts(1), Role::Pauser));
+
+        assert!(roles.grant(&owner, &owner, &accounts(1), Role::SuperAdmin));
+        assert!(roles.grant(&owner, &accounts(1), &accounts(2), Role::Operator));
+        assert!(roles.has_role(&owner, &accounts(1), Role::Pauser));
+        assert!(roles.has_role(&owner, &accounts(2), Role::Operator));
+        assert!(!roles.has_role(&owner, &accounts(2), Role::FeeManager));
+
+        assert!(roles.revoke(&owner, &accounts(1), &accounts(2), Role::Operator));
+        assert!(!roles.has_role(&owner, &accounts(2), Role::Operator));
+    }
+
+    #[test]
+    #[should_panic(expected = "only owner may manage super admins")]
+    fn test_super_admin_grants_super_admin() {
+        testing_env!(VMContextBuilder::new().build());
+        let owner = accounts(0);
+        let mut roles = Roles::new(b"r");
+        roles.grant(&owner, &owner, &accounts(1), Role::SuperAdmin);
+        roles.grant(&owner, &accounts(1), &accounts(2), Role::SuperAdmin);
+    }
+}
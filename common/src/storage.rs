@@ -0,0 +1,348 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::LookupMap;
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, require, AccountId, Balance, IntoStorageKey, Promise, StorageUsage};
+
+/// the storage an account registered with [`StorageAccounts`] takes: the longest account id,
+/// the balances and the overhead of a record
+pub const ACCOUNT_STORAGE_USAGE: StorageUsage = 64 + 16 + 16 + 40;
+
+/// the cost of the storage taken since `initial_storage_usage`; 0 if some has been freed instead
+pub fn storage_cost_since(initial_storage_usage: StorageUsage) -> Balance {
+    let taken = env::storage_usage().saturating_sub(initial_storage_usage);
+    Balance::from(taken) * env::storage_byte_cost()
+}
+
+/// the cost of the storage freed since `initial_storage_usage`; 0 if some has been taken instead
+pub fn storage_refund_since(initial_storage_usage: StorageUsage) -> Balance {
+    let freed = initial_storage_usage.saturating_sub(env::storage_usage());
+    Balance::from(freed) * env::storage_byte_cost()
+}
+
+/// charges `account_id` for the storage taken since `initial_storage_usage`;
+/// `attached_deposit` has to cover `amount`, which the contract keeps, plus the cost,
+/// and what's left gets refunded; returns the cost
+pub fn charge_storage(
+    account_id: &AccountId,
+    attached_deposit: Balance,
+    amount: Balance,
+    initial_storage_usage: StorageUsage,
+) -> Balance {
+    let cost = storage_cost_since(initial_storage_usage);
+    require!(
+        attached_deposit >= amount + cost,
+        format!(
+            "attached deposit ({}) must cover the amount ({}) plus the storage cost ({})",
+            attached_deposit, amount, cost
+        )
+    );
+
+    refund(account_id, attached_deposit - amount - cost);
+    cost
+}
+
+/// sends `amount` back to `account_id`, if there's anything to send
+pub fn refund(account_id: &AccountId, amount: Balance) {
+    if amount > 0 {
+        Promise::new(account_id.clone()).transfer(amount);
+    }
+}
+
+/// the storage balance of an account, as NEP-145 defines it
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageBalance {
+    pub total: U128,
+    pub available: U128,
+}
+
+/// the bounds of a storage balance, as NEP-145 defines them
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageBalanceBounds {
+    pub min: U128,
+    pub max: Option<U128>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy)]
+struct StorageAccount {
+    total: Balance,
+    //the registration itself included
+    used: Balance,
+}
+
+/// the storage deposits of the accounts, NEP-145;
+/// the items the registered accounts create get paid for out of their deposits
+/// rather than out of the attached deposit
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct StorageAccounts {
+    accounts: LookupMap<AccountId, StorageAccount>,
+}
+
+impl StorageAccounts {
+    pub fn new<S: IntoStorageKey>(prefix: S) -> Self {
+        Self {
+            accounts: LookupMap::new(prefix),
+        }
+    }
+
+    /// what it costs to register an account
+    pub fn min_balance() -> Balance {
+        Balance::from(ACCOUNT_STORAGE_USAGE) * env::storage_byte_cost()
+    }
+
+    pub fn bounds() -> StorageBalanceBounds {
+        StorageBalanceBounds {
+            min: U128(Self::min_balance()),
+            max: None,
+        }
+    }
+
+    pub fn is_registered(&self, account_id: &AccountId) -> bool {
+        self.accounts.contains_key(account_id)
+    }
+
+    pub fn balance_of(&self, account_id: &AccountId) -> Option<StorageBalance> {
+        self.accounts.get(account_id).map(|account| StorageBalance {
+            total: U128(account.total),
+            available: U128(account.total - account.used),
+        })
+    }
+
+    /// adds `amount` to the deposit of `account_id`, registering it if need be;
+    /// if `registration_only`, takes just what the registration costs;
+    /// returns the balance and what's left of `amount` to be refunded
+    pub fn deposit(
+        &mut self,
+        account_id: &AccountId,
+        amount: Balance,
+        registration_only: bool,
+    ) -> (StorageBalance, Balance) {
+        let min_balance = Self::min_balance();
+        let (account, refund) = match self.accounts.get(account_id) {
+            Some(account) if registration_only => (account, amount),
+            Some(account) => (
+                StorageAccount {
+                    total: account.total + amount,
+                    ..account
+                },
+                0,
+            ),
+            None => {
+                require!(
+                    amount >= min_balance,
+                    format!("the deposit ({}) must cover the registration ({})", amount, min_balance)
+                );
+                let total = if registration_only { min_balance } else { amount };
+                (
+                    StorageAccount {
+                        total,
+                        used: min_balance,
+                    },
+                    amount - total,
+                )
+            }
+        };
+
+        self.accounts.insert(account_id, &account);
+        (self.balance_of(account_id).unwrap(), refund)
+    }
+
+    /// takes `amount` off the available deposit of `account_id`; all of it, if `amount` isn't provided;
+    /// returns the balance and the amount taken
+    pub fn withdraw(&mut self, account_id: &AccountId, amount: Option<Balance>) -> (StorageBalance, Balance) {
+        let mut account = self.require_account(account_id);
+        let available = account.total - account.used;
+        let amount = amount.unwrap_or(available);
+        require!(
+            amount <= available,
+            format!(
+                "amount ({}) must be equal to or less than the available storage balance ({})",
+                amount, available
+            )
+        );
+
+        account.total -= amount;
+        self.accounts.insert(account_id, &account);
+        (self.balance_of(account_id).unwrap(), amount)
+    }
+
+    /// removes `account_id`, unless its items still take storage;
+    /// returns its whole deposit, or None if it isn't registered
+    pub fn unregister(&mut self, account_id: &AccountId) -> Option<Balance> {
+        let account = self.accounts.get(account_id)?;
+        require!(
+            account.used == Self::min_balance(),
+            format!(
+                "the items of {} still take storage; they have to be removed first",
+                account_id
+            )
+        );
+
+        self.accounts.remove(account_id);
+        Some(account.total)
+    }
+
+    /// charges `account_id` for the storage taken since `initial_storage_usage`, like [`charge_storage`];
+    /// if `account_id` is registered, though, what's attached on top of `amount` gets deposited
+    /// and the cost gets paid for out of the deposit; returns the cost
+    pub fn charge(
+        &mut self,
+        account_id: &AccountId,
+        attached_deposit: Balance,
+        amount: Balance,
+        initial_storage_usage: StorageUsage,
+    ) -> Balance {
+        let mut account = match self.accounts.get(account_id) {
+            Some(account) => account,
+            None => return charge_storage(account_id, attached_deposit, amount, initial_storage_usage),
+        };
+
+        require!(
+            attached_deposit >= amount,
+            format!(
+                "attached deposit ({}) must cover the amount ({})",
+                attached_deposit, amount
+            )
+        );
+        account.total += attached_deposit - amount;
+
+        let cost = storage_cost_since(initial_storage_usage);
+        require!(
+            account.used + cost <= account.total,
+            format!(
+                "the available storage balance ({}) of {} must cover the storage cost ({})",
+                account.total - account.used,
+                account_id,
+                cost
+            )
+        );
+
+        account.used += cost;
+        self.accounts.insert(account_id, &account);
+        cost
+    }
+
+    /// gives `amount` of the freed storage back to `account_id`: to its deposit, if it's registered,
+    /// otherwise by a transfer
+    pub fn release(&mut self, account_id: &AccountId, amount: Balance) {
+        let mut account = match self.accounts.get(account_id) {
+            Some(account) => account,
+            None => return refund(account_id, amount),
+        };
+
+        //the items paid for by the attached deposit, before the registration, take none of the deposit
+        let released = amount.min(account.used - Self::min_balance());
+        account.used -= released;
+        account.total += amount - released;
+        self.accounts.insert(account_id, &account);
+    }
+
+    fn require_account(&self, account_id: &AccountId) -> StorageAccount {
+        let account = self.accounts.get(account_id);
+        require!(account.is_some(), format!("account {} isn't registered", account_id));
+        account.unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    #[test]
+    fn test_storage_cost_and_refund() {
+        testing_env!(VMContextBuilder::new().build());
+        let initial_storage_usage = env::storage_usage();
+        env::storage_write(b"key", b"value");
+        let cost = storage_cost_since(initial_storage_usage);
+        assert!(cost > 0);
+        assert_eq!(cost % env::storage_byte_cost(), 0);
+        assert_eq!(storage_refund_since(initial_storage_usage), 0);
+
+        let taken_storage_usage = env::storage_usage();
+        env::storage_remove(b"key");
+        assert_eq!(storage_refund_since(taken_storage_usage), cost);
+        assert_eq!(storage_cost_since(taken_storage_usage), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "must cover the amount (10) plus the storage cost")]
+    fn test_charge_storage_not_covered() {
+        testing_env!(VMContextBuilder::new().build());
+        let initial_storage_usage = env::storage_usage();
+        env::storage_write(b"key", b"value");
+        charge_storage(&accounts(0), 10, 10, initial_storage_usage);
+    }
+
+    #[test]
+    fn test_deposit_and_withdraw() {
+        testing_env!(VMContextBuilder::new().build());
+        let mut storage_accounts = StorageAccounts::new(b"m");
+        let min_balance = StorageAccounts::min_balance();
+        assert_eq!(storage_accounts.balance_of(&accounts(0)), None);
+
+        let (balance, refund) = storage_accounts.deposit(&accounts(0), min_balance + 100, true);
+        assert_eq!(balance.total.0, min_balance);
+        assert_eq!(balance.available.0, 0);
+        assert_eq!(refund, 100);
+
+        let (balance, refund) = storage_accounts.deposit(&accounts(0), 100, false);
+        assert_eq!(balance.available.0, 100);
+        assert_eq!(refund, 0);
+
+        let (balance, amount) = storage_accounts.withdraw(&accounts(0), None);
+        assert_eq!(balance.available.0, 0);
+        assert_eq!(amount, 100);
+
+        assert_eq!(storage_accounts.unregister(&accounts(0)), Some(min_balance));
+        assert_eq!(storage_accounts.unregister(&accounts(0)), None);
+    }
+
+    #[test]
+    fn test_charge_and_release() {
+        testing_env!(VMContextBuilder::new().build());
+        let mut storage_accounts = StorageAccounts::new(b"m");
+        let min_balance = StorageAccounts::min_balance();
+        storage_accounts.deposit(&accounts(0), min_balance, false);
+
+        //what's attached on top of the amount covers the cost
+        let initial_storage_usage = env::storage_usage();
+        env::storage_write(b"key", b"value");
+        let attached_deposit = 10 + 10u128.pow(22);
+        let cost = storage_accounts.charge(&accounts(0), attached_deposit, 10, initial_storage_usage);
+        let balance = storage_accounts.balance_of(&accounts(0)).unwrap();
+        assert_eq!(balance.total.0, min_balance + 10u128.pow(22));
+        assert_eq!(balance.available.0, 10u128.pow(22) - cost);
+
+        storage_accounts.release(&accounts(0), cost);
+        let balance = storage_accounts.balance_of(&accounts(0)).unwrap();
+        assert_eq!(balance.available.0, 10u128.pow(22));
+        assert_eq!(
+            storage_accounts.unregister(&accounts(0)),
+            Some(min_balance + 10u128.pow(22))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "still take storage")]
+    fn test_unregister_with_items() {
+        testing_env!(VMContextBuilder::new().build());
+        let mut storage_accounts = StorageAccounts::new(b"m");
+        storage_accounts.deposit(&accounts(0), 10u128.pow(23), false);
+        let initial_storage_usage = env::storage_usage();
+        env::storage_write(b"key", b"value");
+        storage_accounts.charge(&accounts(0), 0, 0, initial_storage_usage);
+        storage_accounts.unregister(&accounts(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "must cover the registration")]
+    fn test_deposit_below_registration() {
+        testing_env!(VMContextBuilder::new().build());
+        let mut storage_accounts = StorageAccounts::new(b"m");
+        storage_accounts.deposit(&accounts(0), 1, false);
+    }
+}
@@ -0,0 +1,90 @@
+use crate::ledger::TokenId;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::LookupMap;
+use near_sdk::{require, AccountId, Balance, IntoStorageKey};
+
+/// the fees a contract has collected, kept apart from the funds of the owner;
+/// they get withdrawn to `account_id`
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Treasury {
+    pub account_id: AccountId,
+    accrued: LookupMap<TokenId, Balance>,
+    total_accrued: LookupMap<TokenId, Balance>,
+}
+
+impl Treasury {
+    pub fn new<S: IntoStorageKey>(account_id: AccountId, accrued_prefix: S, total_accrued_prefix: S) -> Self {
+        Self {
+            account_id,
+            accrued: LookupMap::new(accrued_prefix),
+            total_accrued: LookupMap::new(total_accrued_prefix),
+        }
+    }
+
+    /// the fees that haven't been withdrawn yet
+    pub fn accrued_of(&self, token_id: &TokenId) -> Balance {
+        self.accrued.get(token_id).unwrap_or(0)
+    }
+
+    /// all the fees ever collected, the withdrawn ones included
+    pub fn total_accrued_of(&self, token_id: &TokenId) -> Balance {
+        self.total_accrued.get(token_id).unwrap_or(0)
+    }
+
+    pub fn accrue(&mut self, token_id: &TokenId, fee: Balance) {
+        if fee == 0 {
+            return;
+        }
+
+        self.accrued.insert(token_id, &(self.accrued_of(token_id) + fee));
+        self.total_accrued
+            .insert(token_id, &(self.total_accrued_of(token_id) + fee));
+    }
+
+    /// takes `amount` off the accrued fees; all of them, if `amount` isn't provided
+    /// returns the amount taken
+    pub fn take(&mut self, token_id: &TokenId, amount: Option<Balance>) -> Balance {
+        let accrued = self.accrued_of(token_id);
+        let amount = amount.unwrap_or(accrued);
+        require!(
+            amount <= accrued,
+            format!(
+                "amount ({}) must be equal to or less than the accrued fees ({})",
+                amount, accrued
+            )
+        );
+        require!(amount > 0, "there are no fees to withdraw");
+
+        self.accrued.insert(token_id, &(accrued - amount));
+        amount
+    }
+
+    /// puts back the fees of a withdrawal that has failed
+    pub fn restore(&mut self, token_id: &TokenId, amount: Balance) {
+        self.accrued.insert(token_id, &(self.accrued_of(token_id) + amount));
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    #[test]
+    fn test_accrue_and_take() {
+        testing_env!(VMContextBuilder::new().build());
+        let mut treasury = Treasury::new(accounts(0), b"a", b"r");
+
+        treasury.accrue(&None, 100);
+        treasury.accrue(&None, 50);
+        assert_eq!(treasury.take(&None, Some(120)), 120);
+        assert_eq!(treasury.accrued_of(&None), 30);
+
+        treasury.restore(&None, 120);
+        assert_eq!(treasury.take(&None, None), 150);
+        assert_eq!(treasury.accrued_of(&None), 0);
+        assert_eq!(treasury.total_accrued_of(&None), 150);
+    }
+}
@@ -0,0 +1,117 @@
+//! the new code of a contract gets staged first and deployed once a delay has passed,
+//! so that the users may check it before it lands; the code is kept apart from the state,
+//! which is read on every call
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::Base58CryptoHash;
+use near_sdk::serde::Serialize;
+use near_sdk::{env, require, CryptoHash, Gas, Promise};
+
+const CODE_KEY: &[u8] = b"STAGED_CODE";
+const STAGED_CODE_KEY: &[u8] = b"STAGED_CODE_INFO";
+
+/// between staging the code and deploying it; 1 day
+pub const MIN_DEPLOY_DELAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+/// for `migrate`, called once the code has been deployed
+pub const GAS_FOR_MIGRATE: Gas = Gas(100_000_000_000_000);
+
+/// the code staged to be deployed
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StagedCode {
+    // sha256 of the code
+    pub hash: Base58CryptoHash,
+    pub staged_at: u64,
+    pub deployable_at: u64,
+}
+
+/// stages `code`, in place of the code staged before, if any
+pub fn stage(code: &[u8]) -> StagedCode {
+    require!(!code.is_empty(), "the code must not be empty");
+
+    let hash: CryptoHash = env::sha256(code).try_into().unwrap();
+    let staged_at = env::block_timestamp();
+    let staged_code = StagedCode {
+        hash: hash.into(),
+        staged_at,
+        deployable_at: staged_at + MIN_DEPLOY_DELAY,
+    };
+
+    env::storage_write(CODE_KEY, code);
+    env::storage_write(STAGED_CODE_KEY, &staged_code.try_to_vec().unwrap());
+    staged_code
+}
+
+pub fn staged() -> Option<StagedCode> {
+    env::storage_read(STAGED_CODE_KEY).map(|bytes| StagedCode::try_from_slice(&bytes).unwrap())
+}
+
+/// removes the staged code, if any; returns whether there's been any
+pub fn unstage() -> bool {
+    env::storage_remove(CODE_KEY);
+    env::storage_remove(STAGED_CODE_KEY)
+}
+
+/// deploys the staged code, once the delay has passed, and calls `migrate` of the new code
+pub fn deploy() -> (StagedCode, Promise) {
+    let staged_code = staged();
+    require!(staged_code.is_some(), "there's no staged code");
+    let staged_code = staged_code.unwrap();
+    require!(
+        env::block_timestamp() >= staged_code.deployable_at,
+        format!(
+            "the staged code may not be deployed until {}",
+            staged_code.deployable_at
+        )
+    );
+
+    let code = env::storage_read(CODE_KEY).unwrap();
+    unstage();
+
+    let promise = Promise::new(env::current_account_id())
+        .deploy_contract(code)
+        .function_call("migrate".to_string(), Vec::new(), 0, GAS_FOR_MIGRATE);
+    (staged_code, promise)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    #[test]
+    fn test_stage_and_deploy() {
+        testing_env!(VMContextBuilder::new().block_timestamp(100).build());
+        assert_eq!(staged(), None);
+
+        let staged_code = stage(b"code");
+        let hash: CryptoHash = env::sha256(b"code").try_into().unwrap();
+        assert_eq!(staged_code.hash, hash.into());
+        assert_eq!(staged_code.deployable_at, 100 + MIN_DEPLOY_DELAY);
+        assert_eq!(staged(), Some(staged_code));
+
+        testing_env!(VMContextBuilder::new().block_timestamp(100 + MIN_DEPLOY_DELAY).build());
+        let (deployed_code, _) = deploy();
+        assert_eq!(deployed_code, staged_code);
+        assert_eq!(staged(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "may not be deployed until")]
+    fn test_deploy_before_delay() {
+        testing_env!(VMContextBuilder::new().block_timestamp(100).build());
+        stage(b"code");
+        deploy();
+    }
+
+    #[test]
+    fn test_unstage() {
+        testing_env!(VMContextBuilder::new().build());
+        assert!(!unstage());
+        stage(b"code");
+        assert!(unstage());
+        assert_eq!(staged(), None);
+    }
+}
@@ -0,0 +1,112 @@
+//! the values whose layout changes over time get written along with their version,
+//! after a marker no unversioned value begins with; that way the values written
+//! before they got versioned still read, as the layout deployed first, and get upgraded once read
+//!
+//! the version of the layout of the state itself is kept under a key of its own, next to the state
+
+use near_sdk::borsh::{BorshDeserialize, BorshSerialize};
+use near_sdk::{env, require};
+use std::io::{self, Write};
+
+/// the key near_bindgen keeps the state of a contract under
+const STATE_KEY: &[u8] = b"STATE";
+
+/// the key the version of the layout of the state is kept under
+const STATE_VERSION_KEY: &[u8] = b"STATE_VERSION";
+
+/// precedes the version of a value; an unversioned item starts with the length of its id,
+/// which is never that long
+pub const VERSION_MARKER: [u8; 4] = [0xff; 4];
+
+/// the version of a value written before the values got versioned, in the layout deployed first
+pub const UNVERSIONED: u8 = 0;
+
+pub fn write_version<W: Write>(version: u8, writer: &mut W) -> io::Result<()> {
+    writer.write_all(&VERSION_MARKER)?;
+    version.serialize(writer)
+}
+
+/// reads the version written by `write_version`; UNVERSIONED, with nothing read,
+/// if there's no marker
+pub fn read_version(buf: &mut &[u8]) -> io::Result<u8> {
+    if !buf.starts_with(&VERSION_MARKER) {
+        return Ok(UNVERSIONED);
+    }
+
+    *buf = &buf[VERSION_MARKER.len()..];
+    u8::deserialize(buf)
+}
+
+pub fn unknown_version(version: u8) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("unknown version {}", version))
+}
+
+/// the raw state of a contract, for the migration to read in the layout of `read_state_version`
+pub fn read_state() -> Vec<u8> {
+    let state = env::storage_read(STATE_KEY);
+    require!(state.is_some(), "the contract isn't initialized");
+    state.unwrap()
+}
+
+/// the version of the layout of the state, written by `write_state_version`;
+/// 0 for the state of the layout deployed first, which has none
+pub fn read_state_version() -> u32 {
+    env::storage_read(STATE_VERSION_KEY)
+        .map(|version| u32::try_from_slice(&version).unwrap_or_else(|_| env::panic_str("invalid state version")))
+        .unwrap_or(0)
+}
+
+/// records the version of the layout of the state; to be called whenever the state gets initialized or migrated
+pub fn write_state_version(version: u32) {
+    env::storage_write(STATE_VERSION_KEY, &version.try_to_vec().unwrap());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::borsh;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    #[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
+    struct Item {
+        id: String,
+        amount: u128,
+    }
+
+    #[test]
+    fn test_read_unversioned_value() {
+        let item = Item {
+            id: "e1".to_string(),
+            amount: 100,
+        };
+        let bytes = item.try_to_vec().unwrap();
+
+        let mut buf = bytes.as_slice();
+        assert_eq!(read_version(&mut buf).unwrap(), UNVERSIONED);
+        assert_eq!(Item::deserialize(&mut buf).unwrap(), item);
+    }
+
+    #[test]
+    fn test_read_versioned_value() {
+        let item = Item {
+            id: "e1".to_string(),
+            amount: 100,
+        };
+        let mut bytes = Vec::new();
+        write_version(2, &mut bytes).unwrap();
+        item.serialize(&mut bytes).unwrap();
+
+        let mut buf = bytes.as_slice();
+        assert_eq!(read_version(&mut buf).unwrap(), 2);
+        assert_eq!(Item::deserialize(&mut buf).unwrap(), item);
+    }
+
+    #[test]
+    fn test_state_version() {
+        testing_env!(VMContextBuilder::new().build());
+        assert_eq!(read_state_version(), 0);
+        write_state_version(3);
+        assert_eq!(read_state_version(), 3);
+    }
+}
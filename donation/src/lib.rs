@@ -1,41 +1,87 @@
+mod events;
+mod migration;
+
+use antioquia_common::fee_schedule::{FeeSchedule, FeeTier};
+use antioquia_common::fees::{self, FeeLimits, Rounding, HUNDRED_PERCENT_BPS};
+use antioquia_common::index::{self, index_add, index_remove, Backfill, Index};
+use antioquia_common::ledger::ClaimLedger;
+use antioquia_common::pause::{Feature, PauseFlags};
+use antioquia_common::roles::{Role, Roles};
+use antioquia_common::storage::{self, StorageAccounts, StorageBalance, StorageBalanceBounds};
+use antioquia_common::treasury::Treasury;
+use antioquia_common::upgrade::{self, StagedCode};
+use antioquia_common::versioned;
+use events::DonationEvent;
+use migration::{VersionedDonation, VersionedDonationItem, STATE_VERSION};
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::TreeMap;
-use near_sdk::{env, log, near_bindgen, require, AccountId, Balance, PanicOnDefault, Promise};
+use near_sdk::collections::{LookupMap, TreeMap};
+use near_sdk::json_types::{Base64VecU8, U128};
+use near_sdk::{
+    assert_one_yocto, env, near_bindgen, require, AccountId, Balance, Gas, PanicOnDefault, Promise,
+    PromiseResult,
+};
 use std::collections::BTreeMap;
 
+const GAS_FOR_WITHDRAW_CALLBACK: Gas = Gas(10_000_000_000_000);
+
+//generated by the contract out of a counter, see next_donation_id
 type DonationId = String;
 
-#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
+#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault, Clone)]
 pub struct DonationItem {
     pub donation_id: DonationId,
     pub sender_account_id: AccountId,
     pub receiver_account_id: AccountId,
     pub amount: Balance,
     pub current_fee_percent: u128,
+    //a reference of the sender's own, not necessarily unique; the donations get indexed by it
+    pub external_ref: Option<String>, //what the sender has paid for the storage of the donation, released to it once the donation gets removed
+    pub storage_cost: Balance,
 }
 
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
 pub struct Donation {
     pub owner_account_id: AccountId,
+    pub proposed_owner_id: Option<AccountId>,
+    pub roles: Roles,
+    pub pause_flags: PauseFlags,
     pub base_fee_percent: u128,
-    pub items: TreeMap<DonationId, DonationItem>,
+    pub fee_limits: FeeLimits,
+    pub fee_schedule: FeeSchedule,
+    //upgraded to the latest layout once read
+    pub items: TreeMap<DonationId, VersionedDonationItem>,
+    pub claims: ClaimLedger,
+    pub treasury: Treasury,
+    //the storage deposits, NEP-145
+    pub storage_accounts: StorageAccounts,
+    //the last donation_id generated, see next_donation_id
+    pub last_donation_id: u64,
+    pub items_by_external_ref: Index<String, DonationId>,
+    //the donations of the layout deployed first get indexed page by page, see index_old_donations
+    pub index_backfill: Backfill<DonationId>,
 }
 
 #[near_bindgen]
 impl Donation {
     const MIN_FEE_PERCENT: u128 = 0;
-    const MAX_FEE_PERCENT: u128 = 100;
-    const HUNDRED_PERCENT: u128 = 100;
+    const MAX_FEE_PERCENT: u128 = HUNDRED_PERCENT_BPS;
+    const FEE_ROUNDING: Rounding = Rounding::Down;
+    const DEFAULT_PAGE_LIMIT: u64 = 50;
 
+    /// * `treasury_account_id` - where the fees get withdrawn to; the owner, if it isn't provided
     #[init]
-    pub fn init(_owner_account_id: Option<AccountId>, base_fee_percent: Option<u128>) -> Self {
+    pub fn init(
+        _owner_account_id: Option<AccountId>,
+        base_fee_percent: Option<u128>,
+        treasury_account_id: Option<AccountId>,
+    ) -> Self {
         require!(!env::state_exists(), "Already initialized");
         let owner_account_id = _owner_account_id.unwrap_or(env::signer_account_id());
+        let treasury_account_id = treasury_account_id.unwrap_or_else(|| owner_account_id.clone());
         let base_fee_percent2 = base_fee_percent.unwrap_or(Self::MIN_FEE_PERCENT);
         require!(
-            (base_fee_percent2 >= Self::MIN_FEE_PERCENT)
-                && (base_fee_percent2 <= Self::MAX_FEE_PERCENT),
+            (Self::MIN_FEE_PERCENT..=Self::MAX_FEE_PERCENT).contains(&base_fee_percent2),
             format!(
                 "base_fee_percent must be between {}..{}",
                 &Self::MIN_FEE_PERCENT,
@@ -43,51 +89,380 @@ impl Donation {
             )
         );
 
-        let items: TreeMap<DonationId, DonationItem> = TreeMap::new(b"d");
+        let items: TreeMap<DonationId, VersionedDonationItem> = TreeMap::new(b"d");
+        versioned::write_state_version(STATE_VERSION);
         Self {
             owner_account_id,
+            proposed_owner_id: None,
+            roles: Roles::new(b"g"),
+            pause_flags: PauseFlags::default(),
             base_fee_percent: base_fee_percent2,
+            fee_limits: FeeLimits::default(),
+            fee_schedule: FeeSchedule::new(b"o", b"v"),
             items,
+            claims: ClaimLedger::new(b"c"),
+            treasury: Treasury::new(treasury_account_id, b"a", b"r"),
+            storage_accounts: StorageAccounts::new(b"m"),
+            last_donation_id: 0,
+            items_by_external_ref: LookupMap::new(b"x"),
+            index_backfill: Backfill::Done,
+        }
+    }
+
+    /// upgrades the state of an older layout, once new code has been deployed;
+    /// the donations get upgraded one by one, once read
+    ///
+    /// who may call this method
+    ///     * owner
+    ///     * the contract itself
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let state = VersionedDonation::read();
+        let caller = env::predecessor_account_id();
+        require!(
+            &caller == state.owner_account_id() || caller == env::current_account_id(),
+            "only owner may call this method"
+        );
+
+        let from_version = state.version();
+        let donation = Donation::from(state);
+        versioned::write_state_version(STATE_VERSION);
+        DonationEvent::StateMigrate {
+            from_version,
+            to_version: STATE_VERSION,
+        }
+        .emit();
+        donation
+    }
+
+    /// returns the version of the layout of the state
+    pub fn get_state_version(&self) -> u32 {
+        versioned::read_state_version()
+    }
+
+    /// indexes up to `limit` of the donations stored in the layout deployed first, which `migrate` leaves unindexed;
+    /// to be called until it returns true, as the donations show up in the lists only once indexed
+    ///
+    /// who may call this method
+    ///     * owner
+    pub fn index_old_donations(&mut self, limit: Option<u64>) -> bool {
+        require!(
+            self.owner_account_id == env::predecessor_account_id(),
+            "only owner may call this method"
+        );
+
+        let donation_ids = self
+            .index_backfill
+            .next_page(&self.items, limit.unwrap_or(Self::DEFAULT_PAGE_LIMIT));
+        for donation_id in donation_ids {
+            if let Some(VersionedDonationItem::V0(donation)) = self.items.get(&donation_id) {
+                //written back in the latest layout, so that it doesn't get indexed twice
+                let donation = DonationItem::from(donation);
+                self.items.insert(&donation_id, &donation.clone().into());
+                if let Some(external_ref) = &donation.external_ref {
+                    index_add(
+                        &mut self.items_by_external_ref,
+                        b"x",
+                        external_ref,
+                        &donation_id,
+                    );
+                }
+            }
+        }
+
+        self.index_backfill.is_done()
+    }
+
+    /// stages new code of the contract, in place of the code staged before, if any;
+    /// it may be deployed once upgrade::MIN_DEPLOY_DELAY has passed, giving the users time to check it
+    ///
+    /// who may call this method
+    ///     * owner
+    pub fn stage_code(&mut self, code: Base64VecU8) -> StagedCode {
+        require!(
+            self.owner_account_id == env::predecessor_account_id(),
+            "only owner may call this method"
+        );
+
+        let staged_code = upgrade::stage(&code.0);
+        DonationEvent::CodeStage {
+            hash: staged_code.hash,
+            deployable_at: staged_code.deployable_at,
+        }
+        .emit();
+        staged_code
+    }
+
+    /// removes the staged code, if any
+    ///
+    /// who may call this method
+    ///     * owner
+    pub fn unstage_code(&mut self) -> bool {
+        require!(
+            self.owner_account_id == env::predecessor_account_id(),
+            "only owner may call this method"
+        );
+
+        let unstaged = upgrade::unstage();
+        if unstaged {
+            DonationEvent::CodeUnstage {}.emit();
+        }
+
+        unstaged
+    }
+
+    /// deploys the staged code, once the delay has passed, and migrates the state to its layout
+    ///
+    /// who may call this method
+    ///     * owner
+    pub fn deploy_staged_code(&mut self) -> Promise {
+        require!(
+            self.owner_account_id == env::predecessor_account_id(),
+            "only owner may call this method"
+        );
+
+        let (staged_code, promise) = upgrade::deploy();
+        DonationEvent::CodeDeploy {
+            hash: staged_code.hash,
+        }
+        .emit();
+        promise
+    }
+
+    /// returns the staged code, if any: its sha256 hash, to check it against the source, and when it may be deployed
+    pub fn get_staged_code(&self) -> Option<StagedCode> {
+        upgrade::staged()
+    }
+
+    /// returns the Id of the owner
+    pub fn get_owner_id(&self) -> AccountId {
+        self.owner_account_id.clone()
+    }
+
+    /// returns the account proposed to become the owner, if any
+    pub fn get_proposed_owner_id(&self) -> Option<AccountId> {
+        self.proposed_owner_id.clone()
+    }
+
+    /// the first step of transferring the ownership;
+    /// the proposed account becomes the owner once it accepts it
+    pub fn propose_owner(&mut self, proposed_owner_id: AccountId) {
+        require!(
+            self.owner_account_id == env::predecessor_account_id(),
+            "only owner may call this method"
+        );
+        require!(
+            proposed_owner_id != self.owner_account_id,
+            "the proposed account is the owner already"
+        );
+
+        self.proposed_owner_id = Some(proposed_owner_id.clone());
+        DonationEvent::OwnershipPropose {
+            owner_id: self.owner_account_id.clone(),
+            proposed_owner_id,
+        }
+        .emit();
+    }
+
+    /// the second step of transferring the ownership, called by the proposed account
+    pub fn accept_ownership(&mut self) {
+        let caller = env::predecessor_account_id();
+        require!(
+            self.proposed_owner_id.as_ref() == Some(&caller),
+            "only the proposed owner may call this method"
+        );
+
+        let old_owner_id = std::mem::replace(&mut self.owner_account_id, caller.clone());
+        self.proposed_owner_id = None;
+        DonationEvent::OwnershipTransfer {
+            old_owner_id,
+            new_owner_id: caller,
+        }
+        .emit();
+    }
+
+    /// withdraws the proposal; the owner stays the same
+    pub fn cancel_ownership_transfer(&mut self) {
+        require!(
+            self.owner_account_id == env::predecessor_account_id(),
+            "only owner may call this method"
+        );
+
+        match self.proposed_owner_id.take() {
+            Some(proposed_owner_id) => {
+                DonationEvent::OwnershipTransferCancel { proposed_owner_id }.emit();
+            }
+            None => env::panic_str("there's no ownership transfer to cancel"),
+        }
+    }
+
+    /// returns the minimum absolute fee of the donations
+    pub fn get_min_fee(&self) -> U128 {
+        U128(self.fee_limits.min_fee)
+    }
+
+    /// returns the maximum absolute fee of the donations; None if there's no maximum
+    pub fn get_max_fee(&self) -> Option<U128> {
+        self.fee_limits.max_fee.map(U128)
+    }
+
+    /// sets the absolute bounds the fee of the donations gets clamped by
+    pub fn set_fee_limits(&mut self, min_fee: U128, max_fee: Option<U128>) {
+        self.require_role(Role::FeeManager);
+
+        self.fee_limits = FeeLimits::new(min_fee.0, max_fee.map(|f| f.0));
+        DonationEvent::FeeLimitsChange { min_fee, max_fee }.emit();
+    }
+
+    /// returns the fee tiers, by the cumulative volume of the receivers
+    pub fn get_fee_tiers(&self) -> Vec<FeeTier> {
+        self.fee_schedule.tiers().to_vec()
+    }
+
+    /// replaces the fee tiers; they have to be sorted by `min_volume`, ascending
+    pub fn set_fee_tiers(&mut self, tiers: Vec<FeeTier>) {
+        self.require_role(Role::FeeManager);
+
+        self.fee_schedule.set_tiers(tiers.clone());
+        DonationEvent::FeeTiersChange { tiers }.emit();
+    }
+
+    /// returns the fee of an receiver that overrides the tiers, if any
+    pub fn get_fee_override(&self, account_id: AccountId) -> Option<u128> {
+        self.fee_schedule.override_of(&account_id)
+    }
+
+    /// sets the fee of an receiver regardless of the tiers, such as a discount or 0 for an exempt one;
+    /// None removes the override
+    pub fn set_fee_override(&mut self, account_id: AccountId, fee_percent: Option<u128>) {
+        self.require_role(Role::FeeManager);
+
+        self.fee_schedule.set_override(&account_id, fee_percent);
+        DonationEvent::FeeOverrideChange {
+            account_id,
+            fee_percent: fee_percent.map(U128),
         }
+        .emit();
+    }
+
+    /// returns the cumulative volume of an receiver
+    pub fn get_volume(&self, account_id: AccountId) -> U128 {
+        U128(self.fee_schedule.volume_of(&account_id))
+    }
+
+    /// returns the fee, in basis points, a new donation of an receiver would get
+    pub fn get_fee_percent_of(&self, account_id: AccountId) -> u128 {
+        self.fee_schedule
+            .fee_bps_of(&account_id, self.base_fee_percent)
     }
 
     ///makes a donation transaction
+    ///the donation gets credited to the receiver, who then withdraws it;
+    ///the storage of the donation is paid for on top of the amount
+    ///or out of the storage deposit of the sender, if registered;
+    ///returns the donation_id, generated by the contract, and the amounts for the receiver and the fee
+    /// * `external_ref` - optional; a reference of the sender's own, see `list_donations_by_external_ref`
     #[payable]
     pub fn send(
         &mut self,
-        donation_id: DonationId,
+        external_ref: Option<String>,
         receiver_account_id: AccountId,
-        amount: Balance,
-    ) -> (Balance, Balance) {
+        amount: U128,
+    ) -> (DonationId, U128, U128) {
+        self.pause_flags
+            .require_not_paused(Some(Feature::Donations));
+        let amount = amount.0;
         require!(amount > 0, "amount must be greater than 0");
+        require!(
+            amount <= env::attached_deposit(),
+            format!(
+                "amount must be equal to or less than attached deposit: {} and {}",
+                amount,
+                env::attached_deposit()
+            )
+        );
+        let initial_storage_usage = env::storage_usage();
 
-        let donation = DonationItem {
+        let donation_id = self.next_donation_id();
+        let mut donation = DonationItem {
             donation_id: donation_id.clone(),
             sender_account_id: env::predecessor_account_id(),
             receiver_account_id: receiver_account_id.clone(),
-            amount: amount,
-            current_fee_percent: self.base_fee_percent,
+            amount,
+            //the fee is that of the receiver at the moment
+            current_fee_percent: self.get_fee_percent_of(receiver_account_id.clone()),
+            external_ref,
+            //recorded once known, taking no more storage
+            storage_cost: 0,
         };
 
-        self.items.insert(&donation_id.clone(), &donation);
+        self.items.insert(&donation_id, &donation.clone().into());
+        if let Some(external_ref) = &donation.external_ref {
+            index_add(
+                &mut self.items_by_external_ref,
+                b"x",
+                external_ref,
+                &donation_id,
+            );
+        }
+        //the sender pays for the donation alone, not for the credited funds
+        donation.storage_cost = self.storage_accounts.charge(
+            &donation.sender_account_id,
+            env::attached_deposit(),
+            amount,
+            initial_storage_usage,
+        );
+        self.items.insert(&donation_id, &donation.clone().into());
 
-        let amount_for_receiver = donation.amount / Self::HUNDRED_PERCENT
-            * (Self::HUNDRED_PERCENT - donation.current_fee_percent);
-        let amount_for_owner = donation.amount - amount_for_receiver;
+        let fees::FeeSplit {
+            net: amount_for_receiver,
+            fee: amount_for_owner,
+        } = fees::split_clamped(
+            donation.amount,
+            donation.current_fee_percent,
+            &self.fee_limits,
+            Self::FEE_ROUNDING,
+        );
 
-        let p1 = Promise::new(receiver_account_id.clone()).transfer(amount_for_receiver);
-        let p2 = Promise::new(self.owner_account_id.clone()).transfer(amount_for_owner);
+        self.claims
+            .credit(&receiver_account_id, &None, amount_for_receiver);
+        self.fee_schedule.add_volume(&receiver_account_id, amount);
+        self.treasury.accrue(&None, amount_for_owner);
 
-        (amount_for_receiver, amount_for_owner)
+        DonationEvent::DonationSend {
+            donation_id: donation_id.clone(),
+            sender_account_id: donation.sender_account_id,
+            receiver_account_id,
+            amount: U128(amount),
+            fee: U128(amount_for_owner),
+            fee_percent: U128(donation.current_fee_percent),
+            external_ref: donation.external_ref,
+        }
+        .emit();
+
+        (
+            donation_id,
+            U128(amount_for_receiver),
+            U128(amount_for_owner),
+        )
+    }
+
+    /// generates a unique donation_id out of a counter;
+    /// the ids chosen by the senders before are skipped
+    fn next_donation_id(&mut self) -> DonationId {
+        loop {
+            self.last_donation_id += 1;
+            let donation_id = self.last_donation_id.to_string();
+            if !self.items.contains_key(&donation_id) {
+                return donation_id;
+            }
+        }
     }
 
     pub fn get_donation(&self, donation_id: DonationId) -> BTreeMap<String, String> {
-        let donation = self.items.get(&donation_id).unwrap();
+        let donation = DonationItem::from(self.items.get(&donation_id).unwrap());
         let mut tree: BTreeMap<String, String> = BTreeMap::new();
-        tree.insert(
-            String::from("donation_id"),
-            String::from(donation_id.clone()),
-        );
+        tree.insert(String::from("donation_id"), donation_id.clone());
 
         tree.insert(
             String::from("sender_account_id"),
@@ -99,16 +474,663 @@ impl Donation {
             String::from(donation.receiver_account_id),
         );
 
+        tree.insert(String::from("amount"), donation.amount.to_string());
+
         tree.insert(
-            String::from("amount"),
-            String::from(donation.amount.to_string()),
+            String::from("fee_percent"),
+            donation.current_fee_percent.to_string(),
         );
 
         tree.insert(
-            String::from("fee_percent"),
-            String::from(donation.current_fee_percent.to_string()),
+            String::from("external_ref"),
+            donation.external_ref.unwrap_or(String::from("(null)")),
         );
 
         tree
     }
+
+    /// returns the ids of the donations sent with an external reference,
+    /// `limit` of them starting from `from_index`
+    pub fn list_donations_by_external_ref(
+        &self,
+        external_ref: String,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<DonationId> {
+        index::index_page(
+            &self.items_by_external_ref,
+            &external_ref,
+            from_index.unwrap_or(0),
+            limit.unwrap_or(Self::DEFAULT_PAGE_LIMIT),
+        )
+    }
+
+    /// removes a donation, giving the freed storage back to the sender, though no more than it has paid
+    ///
+    /// who may call this method
+    ///     * sender
+    ///     * operator
+    pub fn remove_donation(&mut self, donation_id: DonationId) {
+        self.pause_flags.require_not_paused(None);
+        let donation = self.items.get(&donation_id);
+        require!(
+            donation.is_some(),
+            format!("donation_id '{}' not found", donation_id)
+        );
+        let donation = DonationItem::from(donation.unwrap());
+        require!(
+            donation.sender_account_id == env::predecessor_account_id()
+                || self.has_role(env::predecessor_account_id(), Role::Operator),
+            "only sender or operator may call this method"
+        );
+
+        let initial_storage_usage = env::storage_usage();
+        //one of the layout deployed first isn't indexed until index_old_donations gets to it
+        if let (Some(VersionedDonationItem::V1(_)), Some(external_ref)) =
+            (self.items.remove(&donation_id), &donation.external_ref)
+        {
+            index_remove(&mut self.items_by_external_ref, external_ref, &donation_id);
+        }
+
+        let refund =
+            storage::storage_refund_since(initial_storage_usage).min(donation.storage_cost);
+        self.storage_accounts
+            .release(&donation.sender_account_id, refund);
+    }
+
+    /// withdraws `amount`, or all if it isn't provided, of the donations credited to the caller;
+    /// if the transfer fails, the funds get credited back
+    #[payable]
+    pub fn withdraw(&mut self, amount: Option<U128>) -> Promise {
+        assert_one_yocto();
+        self.pause_flags.require_not_paused(Some(Feature::Payouts));
+        let account_id = env::predecessor_account_id();
+        let amount = self.claims.debit(&account_id, &None, amount.map(|a| a.0));
+        DonationEvent::Withdraw {
+            account_id: account_id.clone(),
+            amount: U128(amount),
+        }
+        .emit();
+
+        Promise::new(account_id.clone()).transfer(amount).then(
+            Self::ext(env::current_account_id())
+                .with_static_gas(GAS_FOR_WITHDRAW_CALLBACK)
+                .on_withdraw(account_id, U128(amount)),
+        )
+    }
+
+    /// callback of `withdraw`; if the transfer has failed, the funds get credited back
+    #[private]
+    pub fn on_withdraw(&mut self, account_id: AccountId, amount: U128) -> bool {
+        if let PromiseResult::Successful(_) = env::promise_result(0) {
+            return true;
+        }
+
+        self.claims.credit(&account_id, &None, amount.0);
+        DonationEvent::WithdrawFail { account_id, amount }.emit();
+        false
+    }
+
+    /// grants a role to an account; the owner has every role implicitly
+    ///
+    /// who may call this method
+    ///     * owner
+    ///     * super admin, except for the super admin role
+    pub fn grant_role(&mut self, account_id: AccountId, role: Role) -> bool {
+        let caller = env::predecessor_account_id();
+        let granted = self
+            .roles
+            .grant(&self.owner_account_id, &caller, &account_id, role);
+        if granted {
+            DonationEvent::RoleGrant {
+                account_id,
+                role,
+                granted_by: caller,
+            }
+            .emit();
+        }
+
+        granted
+    }
+
+    /// revokes a role from an account; who may call this method, the same as of `grant_role`
+    pub fn revoke_role(&mut self, account_id: AccountId, role: Role) -> bool {
+        let caller = env::predecessor_account_id();
+        let revoked = self
+            .roles
+            .revoke(&self.owner_account_id, &caller, &account_id, role);
+        if revoked {
+            DonationEvent::RoleRevoke {
+                account_id,
+                role,
+                revoked_by: caller,
+            }
+            .emit();
+        }
+
+        revoked
+    }
+
+    pub fn has_role(&self, account_id: AccountId, role: Role) -> bool {
+        self.roles
+            .has_role(&self.owner_account_id, &account_id, role)
+    }
+
+    fn set_paused(&mut self, feature: Option<Feature>, paused: bool) -> bool {
+        self.require_role(Role::Pauser);
+        let changed = self.pause_flags.set(feature, paused);
+        if changed {
+            let account_id = env::predecessor_account_id();
+            match paused {
+                true => DonationEvent::Pause {
+                    feature,
+                    account_id,
+                }
+                .emit(),
+                false => DonationEvent::Unpause {
+                    feature,
+                    account_id,
+                }
+                .emit(),
+            }
+        }
+
+        changed
+    }
+
+    fn require_role(&self, role: Role) {
+        self.roles
+            .require_role(&self.owner_account_id, &env::predecessor_account_id(), role);
+    }
+
+    /// pauses `feature` or, if it's None, the whole contract;
+    /// pausing the payouts stops the withdrawals of the credited funds as well
+    pub fn pause(&mut self, feature: Option<Feature>) -> bool {
+        self.set_paused(feature, true)
+    }
+
+    pub fn unpause(&mut self, feature: Option<Feature>) -> bool {
+        self.set_paused(feature, false)
+    }
+
+    /// whether `feature` or, if it's None, the whole contract is paused
+    pub fn is_paused(&self, feature: Option<Feature>) -> bool {
+        self.pause_flags.is_paused(feature)
+    }
+
+    pub fn get_paused_features(&self) -> Vec<Feature> {
+        self.pause_flags.paused_features()
+    }
+
+    /// deposits the attached funds for the storage of `account_id`, the caller by default, registering it if need be;
+    /// if `registration_only`, just what the registration costs gets deposited and the rest refunded
+    #[payable]
+    pub fn storage_deposit(
+        &mut self,
+        account_id: Option<AccountId>,
+        registration_only: Option<bool>,
+    ) -> StorageBalance {
+        let account_id = account_id.unwrap_or_else(env::predecessor_account_id);
+        let (balance, refund) = self.storage_accounts.deposit(
+            &account_id,
+            env::attached_deposit(),
+            registration_only.unwrap_or(false),
+        );
+
+        storage::refund(&env::predecessor_account_id(), refund);
+        balance
+    }
+
+    /// withdraws `amount` of the available storage deposit of the caller; all of it, if amount isn't provided
+    #[payable]
+    pub fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let (balance, amount) = self
+            .storage_accounts
+            .withdraw(&account_id, amount.map(|a| a.0));
+        storage::refund(&account_id, amount);
+        balance
+    }
+
+    /// unregisters the caller and refunds its whole storage deposit, unless it has paid for
+    /// the storage of donations out of it; that storage gets released once the donations get removed,
+    /// see remove_donation;
+    /// returns false if the caller isn't registered
+    #[payable]
+    pub fn storage_unregister(&mut self, force: Option<bool>) -> bool {
+        assert_one_yocto();
+        require!(!force.unwrap_or(false), "force isn't supported");
+
+        let account_id = env::predecessor_account_id();
+        match self.storage_accounts.unregister(&account_id) {
+            Some(total) => {
+                storage::refund(&account_id, total);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
+        self.storage_accounts.balance_of(&account_id)
+    }
+
+    pub fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+        StorageAccounts::bounds()
+    }
+
+    /// returns the account the fees get withdrawn to
+    pub fn get_treasury_account_id(&self) -> AccountId {
+        self.treasury.account_id.clone()
+    }
+
+    pub fn set_treasury_account_id(&mut self, treasury_account_id: AccountId) {
+        require!(
+            self.owner_account_id == env::predecessor_account_id(),
+            "only owner may call this method"
+        );
+
+        self.treasury.account_id = treasury_account_id.clone();
+        DonationEvent::TreasuryChange {
+            treasury_account_id,
+        }
+        .emit();
+    }
+
+    /// returns the fees that haven't been withdrawn yet
+    pub fn get_accrued_fees(&self) -> U128 {
+        U128(self.treasury.accrued_of(&None))
+    }
+
+    /// returns all the fees collected over time, the withdrawn ones included
+    pub fn get_total_accrued_fees(&self) -> U128 {
+        U128(self.treasury.total_accrued_of(&None))
+    }
+
+    /// withdraws `amount`, or all if it isn't provided, of the accrued fees to the treasury;
+    /// if the transfer fails, the fees get restored
+    ///
+    /// who may call this method
+    ///     * owner
+    ///     * treasury
+    #[payable]
+    pub fn withdraw_fees(&mut self, amount: Option<U128>) -> Promise {
+        assert_one_yocto();
+        self.pause_flags.require_not_paused(Some(Feature::Payouts));
+        let caller = env::predecessor_account_id();
+        require!(
+            caller == self.owner_account_id || caller == self.treasury.account_id,
+            "only owner or treasury may call this method"
+        );
+
+        let amount = self.treasury.take(&None, amount.map(|a| a.0));
+        let treasury_account_id = self.treasury.account_id.clone();
+        DonationEvent::FeesWithdraw {
+            treasury_account_id: treasury_account_id.clone(),
+            amount: U128(amount),
+        }
+        .emit();
+
+        Promise::new(treasury_account_id).transfer(amount).then(
+            Self::ext(env::current_account_id())
+                .with_static_gas(GAS_FOR_WITHDRAW_CALLBACK)
+                .on_withdraw_fees(U128(amount)),
+        )
+    }
+
+    /// callback of `withdraw_fees`; if the transfer has failed, the fees get restored
+    #[private]
+    pub fn on_withdraw_fees(&mut self, amount: U128) -> bool {
+        if let PromiseResult::Successful(_) = env::promise_result(0) {
+            return true;
+        }
+
+        self.treasury.restore(&None, amount.0);
+        DonationEvent::FeesWithdrawFail { amount }.emit();
+        false
+    }
+
+    /// returns the funds an account may withdraw
+    pub fn get_claimable_balance(&self, account_id: AccountId) -> U128 {
+        U128(self.claims.balance_of(&account_id, &None))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, RuntimeFeesConfig, VMConfig};
+
+    /// covers the storage of a donation
+    const STORAGE_DEPOSIT: Balance = 10u128.pow(23);
+
+    fn get_context() -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .current_account_id("0.near".parse().unwrap())
+            .signer_account_id(accounts(0))
+            .predecessor_account_id(accounts(0));
+
+        builder
+    }
+
+    fn setup_contract() -> Donation {
+        testing_env!(get_context().build());
+        Donation::init(None, Some(1_000), None)
+    }
+
+    /// sends a donation of 1000 from accounts(0) to accounts(1)
+    fn send_donation(contract: &mut Donation) -> (DonationId, U128, U128) {
+        testing_env!(get_context()
+            .attached_deposit(1000 + STORAGE_DEPOSIT)
+            .build());
+        contract.send(None, accounts(1), U128(1000))
+    }
+
+    /// sets the result of the promise a callback gets called back with
+    fn set_promise_result(result: PromiseResult) {
+        testing_env!(
+            get_context()
+                .predecessor_account_id("0.near".parse().unwrap())
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![result],
+        );
+    }
+
+    #[test]
+    fn test_send() {
+        let mut contract = setup_contract();
+        let (donation_id, amount_for_receiver, fee) = send_donation(&mut contract);
+        assert_eq!((amount_for_receiver.0, fee.0), (900, 100));
+
+        //the donation gets credited to the receiver and the fee kept in the treasury
+        assert_eq!(contract.get_claimable_balance(accounts(1)).0, 900);
+        assert_eq!(contract.get_accrued_fees().0, 100);
+        assert_eq!(contract.get_volume(accounts(1)).0, 1000);
+
+        let donation = contract.get_donation(donation_id);
+        assert_eq!(donation.get("amount"), Some(&"1000".to_string()));
+        assert_eq!(donation.get("fee_percent"), Some(&"1000".to_string()));
+    }
+
+    #[test]
+    fn test_send_with_external_ref() {
+        let mut contract = setup_contract();
+        testing_env!(get_context()
+            .attached_deposit(1000 + STORAGE_DEPOSIT)
+            .build());
+        let (donation_id, _, _) = contract.send(Some("ref".to_string()), accounts(1), U128(1000));
+        assert_eq!(
+            contract.list_donations_by_external_ref("ref".to_string(), None, None),
+            vec![donation_id]
+        );
+    }
+
+    #[test]
+    fn test_fee_gets_clamped() {
+        let mut contract = setup_contract();
+        contract.set_fee_limits(U128(200), None);
+        let (_, amount_for_receiver, fee) = send_donation(&mut contract);
+        assert_eq!((amount_for_receiver.0, fee.0), (800, 200));
+
+        contract.set_fee_limits(U128(0), Some(U128(50)));
+        let (_, amount_for_receiver, fee) = send_donation(&mut contract);
+        assert_eq!((amount_for_receiver.0, fee.0), (950, 50));
+    }
+
+    #[test]
+    fn test_fee_override() {
+        let mut contract = setup_contract();
+        contract.set_fee_override(accounts(1), Some(0));
+        assert_eq!(contract.get_fee_override(accounts(1)), Some(0));
+
+        let (_, amount_for_receiver, fee) = send_donation(&mut contract);
+        assert_eq!((amount_for_receiver.0, fee.0), (1000, 0));
+        assert_eq!(contract.get_accrued_fees().0, 0);
+    }
+
+    #[test]
+    fn test_fee_tiers_by_volume() {
+        let mut contract = setup_contract();
+        contract.set_fee_tiers(vec![FeeTier {
+            min_volume: U128(1000),
+            fee_bps: 500,
+        }]);
+        assert_eq!(contract.get_fee_percent_of(accounts(1)), 1_000);
+
+        //the volume is that of the receiver
+        send_donation(&mut contract);
+        assert_eq!(contract.get_fee_percent_of(accounts(1)), 500);
+        assert_eq!(contract.get_fee_percent_of(accounts(0)), 1_000);
+
+        let (_, amount_for_receiver, fee) = send_donation(&mut contract);
+        assert_eq!((amount_for_receiver.0, fee.0), (950, 50));
+    }
+
+    #[test]
+    #[should_panic(expected = "only owner or fee_manager may call this method")]
+    fn test_set_fee_limits_without_role() {
+        let mut contract = setup_contract();
+        testing_env!(get_context().predecessor_account_id(accounts(1)).build());
+        contract.set_fee_limits(U128(200), None);
+    }
+
+    #[test]
+    fn test_fee_manager_sets_fees() {
+        let mut contract = setup_contract();
+        assert!(contract.grant_role(accounts(2), Role::FeeManager));
+
+        testing_env!(get_context().predecessor_account_id(accounts(2)).build());
+        contract.set_fee_limits(U128(200), Some(U128(300)));
+        assert_eq!(contract.get_min_fee().0, 200);
+        assert_eq!(contract.get_max_fee(), Some(U128(300)));
+
+        testing_env!(get_context().build());
+        assert!(contract.revoke_role(accounts(2), Role::FeeManager));
+        assert!(!contract.has_role(accounts(2), Role::FeeManager));
+    }
+
+    #[test]
+    fn test_withdraw_donation() {
+        let mut contract = setup_contract();
+        send_donation(&mut contract);
+
+        testing_env!(get_context()
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .build());
+        contract.withdraw(Some(U128(400)));
+        assert_eq!(contract.get_claimable_balance(accounts(1)).0, 500);
+
+        //the transfer has failed, so the funds get credited back
+        set_promise_result(PromiseResult::Failed);
+        assert!(!contract.on_withdraw(accounts(1), U128(400)));
+        assert_eq!(contract.get_claimable_balance(accounts(1)).0, 900);
+    }
+
+    #[test]
+    fn test_withdraw_fees_to_treasury() {
+        let mut contract = setup_contract();
+        contract.set_treasury_account_id(accounts(2));
+        assert_eq!(contract.get_treasury_account_id(), accounts(2));
+        send_donation(&mut contract);
+
+        testing_env!(get_context()
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(1)
+            .build());
+        contract.withdraw_fees(None);
+        assert_eq!(contract.get_accrued_fees().0, 0);
+        assert_eq!(contract.get_total_accrued_fees().0, 100);
+
+        //the transfer has failed, so the fees get restored
+        set_promise_result(PromiseResult::Failed);
+        assert!(!contract.on_withdraw_fees(U128(100)));
+        assert_eq!(contract.get_accrued_fees().0, 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "only owner may call this method")]
+    fn test_set_treasury_by_other_account() {
+        let mut contract = setup_contract();
+        testing_env!(get_context().predecessor_account_id(accounts(1)).build());
+        contract.set_treasury_account_id(accounts(1));
+    }
+
+    #[test]
+    fn test_transfer_ownership() {
+        let mut contract = setup_contract();
+        contract.propose_owner(accounts(1));
+        assert_eq!(contract.get_proposed_owner_id(), Some(accounts(1)));
+
+        testing_env!(get_context().predecessor_account_id(accounts(1)).build());
+        contract.accept_ownership();
+        assert_eq!(contract.get_owner_id(), accounts(1));
+        assert_eq!(contract.get_proposed_owner_id(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "only the proposed owner may call this method")]
+    fn test_accept_ownership_by_other_account() {
+        let mut contract = setup_contract();
+        contract.propose_owner(accounts(1));
+
+        testing_env!(get_context().predecessor_account_id(accounts(2)).build());
+        contract.accept_ownership();
+    }
+
+    #[test]
+    #[should_panic(expected = "donations are paused")]
+    fn test_send_while_paused() {
+        let mut contract = setup_contract();
+        contract.grant_role(accounts(3), Role::Pauser);
+        testing_env!(get_context().predecessor_account_id(accounts(3)).build());
+        assert!(contract.pause(Some(Feature::Donations)));
+        assert!(contract.is_paused(Some(Feature::Donations)));
+        send_donation(&mut contract);
+    }
+
+    #[test]
+    #[should_panic(expected = "payouts are paused")]
+    fn test_withdraw_while_payouts_paused() {
+        let mut contract = setup_contract();
+        send_donation(&mut contract);
+        contract.pause(Some(Feature::Payouts));
+
+        testing_env!(get_context()
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .build());
+        contract.withdraw(None);
+    }
+
+    #[test]
+    #[should_panic(expected = "must cover the amount (1000) plus the storage cost")]
+    fn test_storage_not_covered() {
+        let mut contract = setup_contract();
+        testing_env!(get_context().attached_deposit(1000).build());
+        contract.send(None, accounts(1), U128(1000));
+    }
+
+    #[test]
+    fn test_storage_deposit_pays_for_donations() {
+        let mut contract = setup_contract();
+        testing_env!(get_context().attached_deposit(STORAGE_DEPOSIT).build());
+        contract.storage_deposit(None, None);
+        let available = contract
+            .storage_balance_of(accounts(0))
+            .unwrap()
+            .available
+            .0;
+
+        //nothing but the donation is attached
+        testing_env!(get_context().attached_deposit(1000).build());
+        contract.send(None, accounts(1), U128(1000));
+        let balance = contract.storage_balance_of(accounts(0)).unwrap();
+        assert!(balance.available.0 < available);
+        assert_eq!(balance.total.0, STORAGE_DEPOSIT);
+    }
+
+    #[test]
+    fn test_remove_donation_releases_storage() {
+        let mut contract = setup_contract();
+        testing_env!(get_context().attached_deposit(STORAGE_DEPOSIT).build());
+        contract.storage_deposit(None, None);
+        let available = contract
+            .storage_balance_of(accounts(0))
+            .unwrap()
+            .available
+            .0;
+
+        testing_env!(get_context().attached_deposit(1000).build());
+        let (donation_id, _, _) = contract.send(Some("gift".to_string()), accounts(1), U128(1000));
+        contract.remove_donation(donation_id.clone());
+        assert!(contract.items.get(&donation_id).is_none());
+        assert!(contract
+            .list_donations_by_external_ref("gift".to_string(), None, None)
+            .is_empty());
+        assert_eq!(
+            contract
+                .storage_balance_of(accounts(0))
+                .unwrap()
+                .available
+                .0,
+            available
+        );
+
+        //the donation stays credited to the receiver
+        assert_eq!(contract.get_claimable_balance(accounts(1)).0, 900);
+        testing_env!(get_context().attached_deposit(1).build());
+        assert!(contract.storage_unregister(None));
+    }
+
+    #[test]
+    #[should_panic(expected = "only sender or operator may call this method")]
+    fn test_remove_donation_by_other_account() {
+        let mut contract = setup_contract();
+        let (donation_id, _, _) = send_donation(&mut contract);
+        testing_env!(get_context().predecessor_account_id(accounts(1)).build());
+        contract.remove_donation(donation_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "still take storage")]
+    fn test_storage_unregister_with_donations() {
+        let mut contract = setup_contract();
+        testing_env!(get_context().attached_deposit(STORAGE_DEPOSIT).build());
+        contract.storage_deposit(None, None);
+        testing_env!(get_context().attached_deposit(1000).build());
+        contract.send(None, accounts(1), U128(1000));
+
+        testing_env!(get_context().attached_deposit(1).build());
+        contract.storage_unregister(None);
+    }
+
+    #[test]
+    fn test_stage_and_deploy_code() {
+        let mut contract = setup_contract();
+        testing_env!(get_context().block_timestamp(100).build());
+        let staged_code = contract.stage_code(Base64VecU8(b"code".to_vec()));
+        assert_eq!(contract.get_staged_code(), Some(staged_code));
+        assert_eq!(staged_code.deployable_at, 100 + upgrade::MIN_DEPLOY_DELAY);
+
+        testing_env!(get_context()
+            .block_timestamp(staged_code.deployable_at)
+            .build());
+        contract.deploy_staged_code();
+        assert_eq!(contract.get_staged_code(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "may not be deployed until")]
+    fn test_deploy_code_before_delay() {
+        let mut contract = setup_contract();
+        contract.stage_code(Base64VecU8(b"code".to_vec()));
+        contract.deploy_staged_code();
+    }
 }
@@ -0,0 +1,297 @@
+use crate::*;
+use antioquia_common::versioned::{self, read_version, unknown_version, write_version};
+use std::io::{self, Write};
+
+/// the version of the layout of the contract state; stored under a key of its own,
+/// which `migrate` reads, see `versioned::write_state_version`
+pub const STATE_VERSION: u32 = 1;
+
+/// a DonationItem as it was deployed first, unversioned; `current_fee_percent` is in percent
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct DonationItemV0 {
+    pub donation_id: DonationId,
+    pub sender_account_id: AccountId,
+    pub receiver_account_id: AccountId,
+    pub amount: Balance,
+    pub current_fee_percent: u128,
+}
+
+/// a donation as stored, in any of its layouts;
+/// it gets upgraded to DonationItem once read and stored as such once written back
+pub enum VersionedDonationItem {
+    V0(DonationItemV0),
+    V1(DonationItem),
+}
+
+impl BorshSerialize for VersionedDonationItem {
+    fn serialize<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        match self {
+            Self::V0(item) => {
+                write_version(0, writer)?;
+                item.serialize(writer)
+            }
+            Self::V1(item) => {
+                write_version(1, writer)?;
+                item.serialize(writer)
+            }
+        }
+    }
+}
+
+impl BorshDeserialize for VersionedDonationItem {
+    fn deserialize(buf: &mut &[u8]) -> io::Result<Self> {
+        match read_version(buf)? {
+            0 => Ok(Self::V0(DonationItemV0::deserialize(buf)?)),
+            1 => Ok(Self::V1(DonationItem::deserialize(buf)?)),
+            version => Err(unknown_version(version)),
+        }
+    }
+}
+
+impl From<DonationItem> for VersionedDonationItem {
+    fn from(item: DonationItem) -> Self {
+        Self::V1(item)
+    }
+}
+
+impl From<DonationItemV0> for DonationItem {
+    fn from(item: DonationItemV0) -> Self {
+        Self {
+            //the donations sent before had the sender choose their donation_id, which serves as their reference
+            external_ref: Some(item.donation_id.clone()),
+            donation_id: item.donation_id,
+            sender_account_id: item.sender_account_id,
+            receiver_account_id: item.receiver_account_id,
+            amount: item.amount,
+            current_fee_percent: item.current_fee_percent * fees::BPS_PER_PERCENT,
+            //the contract has paid for the storage
+            storage_cost: 0,
+        }
+    }
+}
+
+impl From<VersionedDonationItem> for DonationItem {
+    fn from(item: VersionedDonationItem) -> Self {
+        match item {
+            VersionedDonationItem::V0(item) => item.into(),
+            VersionedDonationItem::V1(item) => item,
+        }
+    }
+}
+
+/// the contract state as it was deployed first; `base_fee_percent` is in percent
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct DonationV0 {
+    pub owner_account_id: AccountId,
+    pub base_fee_percent: u128,
+    //the layout of a collection doesn't depend on the type of its values
+    pub items: TreeMap<DonationId, VersionedDonationItem>,
+}
+
+/// the contract state in any of its layouts
+#[allow(clippy::large_enum_variant)]
+pub enum VersionedDonation {
+    V0(DonationV0),
+    V1(Donation),
+}
+
+impl VersionedDonation {
+    /// reads the state in the layout of its version, see `versioned::read_state_version`
+    pub fn read() -> Self {
+        let state = versioned::read_state();
+        let result = match versioned::read_state_version() {
+            0 => DonationV0::try_from_slice(&state).map(Self::V0),
+            1 => Donation::try_from_slice(&state).map(Self::V1),
+            version => env::panic_str(&format!("the state is of an unknown version {}", version)),
+        };
+        result.unwrap_or_else(|_| env::panic_str("the state doesn't match its version"))
+    }
+
+    pub fn version(&self) -> u32 {
+        match self {
+            Self::V0(_) => 0,
+            Self::V1(_) => STATE_VERSION,
+        }
+    }
+
+    pub fn owner_account_id(&self) -> &AccountId {
+        match self {
+            Self::V0(donation) => &donation.owner_account_id,
+            Self::V1(donation) => &donation.owner_account_id,
+        }
+    }
+}
+
+impl From<DonationV0> for Donation {
+    fn from(donation: DonationV0) -> Self {
+        Self {
+            treasury: Treasury::new(donation.owner_account_id.clone(), b"a", b"r"),
+            owner_account_id: donation.owner_account_id,
+            proposed_owner_id: None,
+            roles: Roles::new(b"g"),
+            pause_flags: PauseFlags::default(),
+            base_fee_percent: donation.base_fee_percent * fees::BPS_PER_PERCENT,
+            fee_limits: FeeLimits::default(),
+            fee_schedule: FeeSchedule::new(b"o", b"v"),
+            items: donation.items,
+            claims: ClaimLedger::new(b"c"),
+            storage_accounts: StorageAccounts::new(b"m"),
+            //the donation_ids taken by then get skipped, see Donation::next_donation_id
+            last_donation_id: 0,
+            //the donations sent before get indexed page by page, see Donation::index_old_donations
+            items_by_external_ref: LookupMap::new(b"x"),
+            index_backfill: Backfill::Pending { after: None },
+        }
+    }
+}
+
+impl From<VersionedDonation> for Donation {
+    fn from(state: VersionedDonation) -> Self {
+        match state {
+            VersionedDonation::V0(donation) => donation.into(),
+            VersionedDonation::V1(donation) => donation,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    fn donation_item_v0(donation_id: &str) -> DonationItemV0 {
+        DonationItemV0 {
+            donation_id: donation_id.to_string(),
+            sender_account_id: accounts(0),
+            receiver_account_id: accounts(1),
+            amount: 1000,
+            //percent
+            current_fee_percent: 10,
+        }
+    }
+
+    /// writes a state of the layout deployed first with a single donation, of `donation_id` chosen by its sender
+    fn write_state_v0(donation_id: &str) {
+        let mut items: TreeMap<DonationId, DonationItemV0> = TreeMap::new(b"d");
+        items.insert(&donation_id.to_string(), &donation_item_v0(donation_id));
+
+        let state = DonationV0 {
+            owner_account_id: accounts(0),
+            //percent
+            base_fee_percent: 5,
+            items: TreeMap::try_from_slice(&items.try_to_vec().unwrap()).unwrap(),
+        };
+        env::state_write(&state);
+    }
+
+    #[test]
+    fn test_read_donation_item_of_baseline_layout() {
+        //written before the donations got versioned
+        let bytes = donation_item_v0("d0").try_to_vec().unwrap();
+        let item = DonationItem::from(VersionedDonationItem::try_from_slice(&bytes).unwrap());
+        assert_eq!(item.donation_id, "d0");
+        assert_eq!(item.receiver_account_id, accounts(1));
+        assert_eq!(item.amount, 1000);
+        assert_eq!(item.current_fee_percent, 1_000);
+        assert_eq!(item.external_ref, Some("d0".to_string()));
+
+        //written back in the latest layout
+        let bytes = VersionedDonationItem::from(item).try_to_vec().unwrap();
+        assert!(bytes.starts_with(&versioned::VERSION_MARKER));
+        assert!(matches!(
+            VersionedDonationItem::try_from_slice(&bytes).unwrap(),
+            VersionedDonationItem::V1(_)
+        ));
+    }
+
+    #[test]
+    fn test_migrate_state_of_baseline_layout() {
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(0))
+            .build());
+        write_state_v0("d0");
+
+        let mut contract = Donation::migrate();
+        assert_eq!(contract.get_state_version(), STATE_VERSION);
+        assert_eq!(versioned::read_state_version(), STATE_VERSION);
+        assert_eq!(contract.get_owner_id(), accounts(0));
+        assert_eq!(contract.get_fee_percent_of(accounts(3)), 500);
+        assert_eq!(contract.get_treasury_account_id(), accounts(0));
+        assert_eq!(
+            contract.get_donation("d0".to_string()).get("fee_percent"),
+            Some(&"1000".to_string())
+        );
+
+        //the donation_id chosen by the sender serves as the reference, once indexed
+        assert!(contract
+            .list_donations_by_external_ref("d0".to_string(), None, None)
+            .is_empty());
+        assert!(!contract.index_old_donations(Some(1)));
+        assert!(contract.index_old_donations(Some(1)));
+        assert!(contract.index_old_donations(Some(1)));
+        assert_eq!(
+            contract.list_donations_by_external_ref("d0".to_string(), None, None),
+            vec!["d0".to_string()]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "only owner may call this method")]
+    fn test_index_old_donations_by_other_account() {
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(0))
+            .build());
+        write_state_v0("d0");
+        let mut contract = Donation::migrate();
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.index_old_donations(None);
+    }
+
+    #[test]
+    fn test_generated_ids_skip_those_of_old_layout() {
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        write_state_v0("1");
+
+        let mut contract = Donation::migrate();
+        let (donation_id, _, _) = contract.send(Some("gift".to_string()), accounts(1), U128(1000));
+        assert_eq!(donation_id, "2");
+        assert_eq!(
+            contract.list_donations_by_external_ref("gift".to_string(), None, None),
+            vec!["2".to_string()]
+        );
+        assert_eq!(
+            contract.get_donation("1".to_string()).get("amount"),
+            Some(&"1000".to_string())
+        );
+    }
+
+    #[test]
+    fn test_migrate_state_of_latest_layout() {
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(0))
+            .build());
+        let contract = Donation::init(Some(accounts(0)), Some(1_000), None);
+        env::state_write(&contract);
+
+        let contract = Donation::migrate();
+        assert_eq!(contract.get_state_version(), STATE_VERSION);
+        assert_eq!(contract.get_fee_percent_of(accounts(3)), 1_000);
+    }
+
+    #[test]
+    #[should_panic(expected = "only owner may call this method")]
+    fn test_migrate_by_other_account() {
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(1))
+            .build());
+        write_state_v0("d0");
+        Donation::migrate();
+    }
+}
@@ -0,0 +1,182 @@
+use crate::EscrowId;
+use antioquia_common::events::emit_event;
+use antioquia_common::fee_schedule::FeeTier;
+use antioquia_common::pause::Feature;
+use antioquia_common::roles::Role;
+use near_sdk::json_types::{Base58CryptoHash, U128};
+use near_sdk::serde::Serialize;
+use near_sdk::AccountId;
+
+const EVENT_STANDARD: &str = "antioquia_escrow";
+const EVENT_VERSION: &str = "1.0.0";
+
+/// a transfer credited to an account
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Payout {
+    pub account_id: AccountId,
+    pub amount: U128,
+}
+
+/// NEP-297 events of the escrow;
+/// `token_account_id` is None for NEAR
+#[derive(Serialize)]
+#[serde(
+    crate = "near_sdk::serde",
+    tag = "event",
+    content = "data",
+    rename_all = "snake_case"
+)]
+pub enum EscrowEvent {
+    EscrowCreate {
+        escrow_id: EscrowId,
+        funder_account_id: AccountId,
+        beneficiary_account_id: AccountId,
+        agreed_amount: U128,
+        deposit: U128,
+        fee_percent: U128,
+        token_account_id: Option<AccountId>,
+        external_ref: Option<String>,
+    },
+    EscrowFund {
+        escrow_id: EscrowId,
+        account_id: AccountId,
+        amount: U128,
+        current_amount: U128,
+        token_account_id: Option<AccountId>,
+    },
+    EscrowRelease {
+        escrow_id: EscrowId,
+        amount: U128,
+        fee: U128,
+        fee_percent: U128,
+        payouts: Vec<Payout>,
+        token_account_id: Option<AccountId>,
+    },
+    EscrowReimburse {
+        escrow_id: EscrowId,
+        funder_account_id: AccountId,
+        amount: U128,
+        token_account_id: Option<AccountId>,
+    },
+    EmergencyWithdrawalAnnounce {
+        escrow_id: EscrowId,
+        reason: String,
+        account_id: AccountId,
+        executable_at: u64,
+    },
+    EmergencyWithdrawalCancel {
+        escrow_id: EscrowId,
+    },
+    EmergencyWithdrawalDelayChange {
+        old_delay: u64,
+        new_delay: u64,
+    },
+    EscrowEmergencyWithdraw {
+        escrow_id: EscrowId,
+        account_id: AccountId,
+        amount: U128,
+        reason: String,
+        token_account_id: Option<AccountId>,
+    },
+    Withdraw {
+        account_id: AccountId,
+        amount: U128,
+        token_account_id: Option<AccountId>,
+    },
+    WithdrawFail {
+        account_id: AccountId,
+        amount: U128,
+        token_account_id: Option<AccountId>,
+    },
+    RefundWithdraw {
+        account_id: AccountId,
+        amount: U128,
+        token_account_id: Option<AccountId>,
+    },
+    RefundWithdrawFail {
+        account_id: AccountId,
+        amount: U128,
+        token_account_id: Option<AccountId>,
+    },
+    FeesWithdraw {
+        treasury_account_id: AccountId,
+        amount: U128,
+        token_account_id: Option<AccountId>,
+    },
+    FeesWithdrawFail {
+        amount: U128,
+        token_account_id: Option<AccountId>,
+    },
+    TreasuryChange {
+        treasury_account_id: AccountId,
+    },
+    TokenAccept {
+        token_account_id: AccountId,
+    },
+    TokenRemove {
+        token_account_id: AccountId,
+    },
+    RoleGrant {
+        account_id: AccountId,
+        role: Role,
+        granted_by: AccountId,
+    },
+    RoleRevoke {
+        account_id: AccountId,
+        role: Role,
+        revoked_by: AccountId,
+    },
+    Pause {
+        feature: Option<Feature>,
+        account_id: AccountId,
+    },
+    Unpause {
+        feature: Option<Feature>,
+        account_id: AccountId,
+    },
+    FeeChange {
+        old_fee_percent: U128,
+        new_fee_percent: U128,
+    },
+    OwnershipPropose {
+        owner_id: AccountId,
+        proposed_owner_id: AccountId,
+    },
+    OwnershipTransfer {
+        old_owner_id: AccountId,
+        new_owner_id: AccountId,
+    },
+    OwnershipTransferCancel {
+        proposed_owner_id: AccountId,
+    },
+    FeeLimitsChange {
+        min_fee: U128,
+        max_fee: Option<U128>,
+    },
+    FeeTiersChange {
+        tiers: Vec<FeeTier>,
+    },
+    FeeOverrideChange {
+        account_id: AccountId,
+        fee_percent: Option<U128>,
+    },
+    StateMigrate {
+        from_version: u32,
+        to_version: u32,
+    },
+    CodeStage {
+        hash: Base58CryptoHash,
+        deployable_at: u64,
+    },
+    CodeUnstage {},
+    CodeDeploy {
+        hash: Base58CryptoHash,
+    },
+}
+
+impl EscrowEvent {
+    pub fn emit(&self) {
+        emit_event(EVENT_STANDARD, EVENT_VERSION, self);
+    }
+}
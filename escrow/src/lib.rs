@@ -1,21 +1,140 @@
+mod events;
+mod migration;
+
+use antioquia_common::fee_schedule::{FeeSchedule, FeeTier};
+use antioquia_common::fees::{self, FeeLimits, Rounding, HUNDRED_PERCENT_BPS};
+use antioquia_common::index::{self, index_add, index_remove, Backfill, Index};
+use antioquia_common::ledger::ClaimLedger;
+use antioquia_common::pause::{Feature, PauseFlags};
+use antioquia_common::roles::{Role, Roles};
+use antioquia_common::storage::{self, StorageAccounts, StorageBalance, StorageBalanceBounds};
+use antioquia_common::treasury::Treasury;
+use antioquia_common::upgrade::{self, StagedCode};
+use antioquia_common::versioned;
+use events::{EscrowEvent, Payout};
+use migration::{VersionedEscrow, VersionedEscrowItem, STATE_VERSION};
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::TreeMap;
-use near_sdk::{env, log, near_bindgen, require, AccountId, Balance, PanicOnDefault, Promise};
+use near_sdk::collections::{LookupMap, LookupSet, TreeMap, UnorderedSet};
+use near_sdk::json_types::{Base64VecU8, U128};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{
+    assert_one_yocto, env, ext_contract, log, near_bindgen, require, serde_json, AccountId, Balance, Gas,
+    PanicOnDefault, Promise, PromiseOrValue, PromiseResult,
+};
 
 type EscrowId = String;
 
-#[derive(BorshDeserialize, BorshSerialize, PartialEq, Debug)]
+const GAS_FOR_FT_TRANSFER: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_WITHDRAW_CALLBACK: Gas = Gas(10_000_000_000_000);
+
+#[ext_contract(ext_ft)]
+pub trait FungibleToken {
+    fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>);
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
 pub enum Status {
     New,
     Active,
     PayedOff,
     Reimbursed,
+    // frozen until the arbiter resolves it
+    Disputed,
+    // the arbiter has split the funds between beneficiary and funder
+    Resolved,
+    // funder and beneficiary, or owner, have agreed on splitting the funds between them
+    Settled,
+    // the funder has withdrawn an escrow that hadn't been fully funded
+    Canceled,
+}
+
+impl Status {
+    /// whether the escrow has come to an end, with no funds left in it
+    pub fn is_final(&self) -> bool {
+        !matches!(self, Status::New | Status::Active | Status::Disputed)
+    }
 }
 
 //TODO add a separate status for 'EscrowFundsStatus'
 //
 
-#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum MilestoneStatus {
+    Pending,
+    Released,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Clone)]
+pub struct Milestone {
+    pub name: String,
+    pub amount: Balance,
+    pub status: MilestoneStatus,
+}
+
+/// a split proposed by one of the parties, awaiting the consent of the other one
+#[derive(BorshDeserialize, BorshSerialize, Clone)]
+pub struct SettlementProposal {
+    pub proposed_by: AccountId,
+    pub amount_for_beneficiary: Balance,
+}
+
+/// one of several beneficiaries of an escrow and his share of it, in basis points
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BeneficiaryShare {
+    pub account_id: AccountId,
+    pub share_bps: u128,
+}
+
+/// a milestone as passed to `Escrow::new`
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MilestoneArgs {
+    pub name: String,
+    pub amount: U128,
+}
+
+/// the arguments of `Escrow::new`
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NewEscrowArgs {
+    pub external_ref: Option<String>,
+    pub funder_account_id: AccountId,
+    pub beneficiary_account_id: AccountId,
+    pub agreed_amount: U128,
+    pub milestones: Option<Vec<MilestoneArgs>>,
+    pub release_deadline_at: Option<u64>,
+    pub arbiter_account_id: Option<AccountId>,
+    pub beneficiaries: Option<Vec<BeneficiaryShare>>,
+    pub approvers: Option<Vec<AccountId>>,
+    pub approval_threshold: Option<u32>,
+}
+
+/// an emergency withdrawal announced by the owner;
+/// it may be executed once `executable_at` has come, unless the escrow gets settled before
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct EmergencyWithdrawal {
+    pub reason: String,
+    // one of the parties of the escrow the funds go to
+    pub account_id: AccountId,
+    pub announced_at: u64,
+    pub executable_at: u64,
+}
+
+/// `msg` of `ft_transfer_call` to this contract, e.g.
+///     {"new": {"external_ref": "...", "funder_account_id": "...", ...}}
+///     {"deposit": {"escrow_id": "..."}}
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+pub enum FtTransferMessage {
+    New(Box<NewEscrowArgs>),
+    Deposit { escrow_id: EscrowId },
+}
+
+#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault, Clone)]
 pub struct EscrowItem {
     pub escrow_id: EscrowId,
     pub status: Status,
@@ -25,36 +144,197 @@ pub struct EscrowItem {
     pub current_amount: Balance,
     pub current_fee_percent: u128,
 
-    // pub inserted_at: u64,
-    // pub funded_at: u64,
-    // pub finished_at: u64,
+    // empty for an escrow that's released all at once
+    pub milestones: Vec<Milestone>,
+    // nanoseconds, env::block_timestamp()
+    pub inserted_at: u64,
+    // after it, the funder may reclaim the funds of an Active escrow by himself
+    pub release_deadline_at: Option<u64>,
+    // resolves disputes, if any
+    pub arbiter_account_id: Option<AccountId>,
+    // empty if beneficiary_account_id is the only one;
+    // otherwise, beneficiary_account_id is one of them and receives the dust left after splitting
+    pub beneficiaries: Vec<BeneficiaryShare>,
+    // approval_threshold of approvers must approve a release; empty if none is needed
+    pub approvers: Vec<AccountId>,
+    pub approval_threshold: u32,
+    pub approvals: Vec<AccountId>,
+    pub settlement_proposal: Option<SettlementProposal>,
+    // set once current_amount reaches agreed_amount
+    pub funded_at: Option<u64>,
+    // NEP-141 token contract the escrow is funded with; None for NEAR
+    pub token_account_id: Option<AccountId>,
+    pub emergency_withdrawal: Option<EmergencyWithdrawal>,
+    // has paid for the storage of the escrow and gets it back once the escrow is removed;
+    // None if the contract has paid for it
+    pub storage_payer_id: Option<AccountId>,
+    // set once the escrow has come to an end; None for the escrows finished before it got recorded
+    pub finished_at: Option<u64>,
+    // a reference of the caller's own, not necessarily unique; the escrows get indexed by it
+    pub external_ref: Option<String>,
+    // what storage_payer_id has been charged for the storage; no more than it gets refunded
+    pub storage_cost: Balance,
+}
+
+/// a Milestone as returned by the views
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MilestoneView {
+    pub name: String,
+    pub amount: U128,
+    pub status: MilestoneStatus,
+}
+
+impl From<Milestone> for MilestoneView {
+    fn from(milestone: Milestone) -> Self {
+        Self {
+            name: milestone.name,
+            amount: U128(milestone.amount),
+            status: milestone.status,
+        }
+    }
+}
+
+/// a SettlementProposal as returned by the views
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SettlementProposalView {
+    pub proposed_by: AccountId,
+    pub amount_for_beneficiary: U128,
+}
+
+impl From<SettlementProposal> for SettlementProposalView {
+    fn from(proposal: SettlementProposal) -> Self {
+        Self {
+            proposed_by: proposal.proposed_by,
+            amount_for_beneficiary: U128(proposal.amount_for_beneficiary),
+        }
+    }
+}
+
+/// an EscrowItem as returned by the views; the amounts are strings, as JSON numbers can't hold them
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct EscrowView {
+    pub escrow_id: EscrowId,
+    pub status: Status,
+    pub funder_account_id: AccountId,
+    pub beneficiary_account_id: AccountId,
+    pub agreed_amount: U128,
+    pub current_amount: U128,
+    pub current_fee_percent: U128,
+    pub milestones: Vec<MilestoneView>,
+    pub inserted_at: u64,
+    pub funded_at: Option<u64>,
+    pub release_deadline_at: Option<u64>,
+    pub arbiter_account_id: Option<AccountId>,
+    pub beneficiaries: Vec<BeneficiaryShare>,
+    pub approvers: Vec<AccountId>,
+    pub approval_threshold: u32,
+    pub approvals: Vec<AccountId>,
+    pub settlement_proposal: Option<SettlementProposalView>,
+    pub token_account_id: Option<AccountId>,
+    pub emergency_withdrawal: Option<EmergencyWithdrawal>,
+    pub storage_payer_id: Option<AccountId>,
+    pub finished_at: Option<u64>,
+    pub external_ref: Option<String>,
+    pub storage_cost: U128,
+}
+
+impl From<EscrowItem> for EscrowView {
+    fn from(item: EscrowItem) -> Self {
+        Self {
+            escrow_id: item.escrow_id,
+            status: item.status,
+            funder_account_id: item.funder_account_id,
+            beneficiary_account_id: item.beneficiary_account_id,
+            agreed_amount: U128(item.agreed_amount),
+            current_amount: U128(item.current_amount),
+            current_fee_percent: U128(item.current_fee_percent),
+            milestones: item.milestones.into_iter().map(MilestoneView::from).collect(),
+            inserted_at: item.inserted_at,
+            funded_at: item.funded_at,
+            release_deadline_at: item.release_deadline_at,
+            arbiter_account_id: item.arbiter_account_id,
+            beneficiaries: item.beneficiaries,
+            approvers: item.approvers,
+            approval_threshold: item.approval_threshold,
+            approvals: item.approvals,
+            settlement_proposal: item.settlement_proposal.map(SettlementProposalView::from),
+            token_account_id: item.token_account_id,
+            emergency_withdrawal: item.emergency_withdrawal,
+            storage_payer_id: item.storage_payer_id,
+            finished_at: item.finished_at,
+            external_ref: item.external_ref,
+            storage_cost: U128(item.storage_cost),
+        }
+    }
 }
 
+/// escrow ids by an account, status or external reference, for the list_escrows_by_* views
+type EscrowIndex<K> = Index<K, EscrowId>;
+
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
 pub struct Escrow {
     pub owner_id: AccountId,
+    pub proposed_owner_id: Option<AccountId>,
+    pub roles: Roles,
+    pub pause_flags: PauseFlags,
     pub base_fee_percent: u128,
-    pub items: TreeMap<EscrowId, EscrowItem>,
+    pub fee_limits: FeeLimits,
+    pub fee_schedule: FeeSchedule,
+    // upgraded to the latest layout once read, see read_item
+    pub items: TreeMap<EscrowId, VersionedEscrowItem>,
+    pub claims: ClaimLedger,
+    pub treasury: Treasury,
+    pub items_by_funder: EscrowIndex<AccountId>,
+    pub items_by_beneficiary: EscrowIndex<AccountId>,
+    pub items_by_status: EscrowIndex<Status>,
+    // nanoseconds between announcing an emergency withdrawal and executing it
+    pub emergency_withdrawal_delay: u64,
+    // the storage deposits, NEP-145
+    pub storage_accounts: StorageAccounts,
+    // the last escrow_id generated, see next_escrow_id
+    pub last_escrow_id: u64,
+    pub items_by_external_ref: EscrowIndex<String>,
+    // the escrows of the layout deployed first get indexed page by page, see index_old_escrows
+    pub index_backfill: Backfill<EscrowId>,
+    // the NEP-141 tokens ft_on_transfer accepts; any contract may call it, posing as a token
+    pub accepted_tokens: UnorderedSet<AccountId>,
+    // the accounts whose storage deposits may pay for the escrows they create with ft_transfer_call
+    pub ft_storage_authorized: LookupSet<AccountId>,
+    // the funds refunded to the funders, kept apart from the payouts so that they may be withdrawn while those are paused
+    pub refunds: ClaimLedger,
 }
 
 #[near_bindgen]
 impl Escrow {
     const MIN_FEE_PERCENT: u128 = 0;
-    const MAX_FEE_PERCENT: u128 = 100;
-    const HUNDRED_PERCENT: u128 = 100;
+    const MAX_FEE_PERCENT: u128 = HUNDRED_PERCENT_BPS;
+    const FEE_ROUNDING: Rounding = Rounding::Down;
+    const DEFAULT_PAGE_LIMIT: u64 = 50;
+    // 3 days
+    const DEFAULT_EMERGENCY_WITHDRAWAL_DELAY: u64 = 3 * 24 * 60 * 60 * 1_000_000_000;
+    const MIN_EMERGENCY_WITHDRAWAL_DELAY: u64 = 24 * 60 * 60 * 1_000_000_000;
 
     /// initialize Escrow globally;
     /// it has to be called only once;
-    /// * `base_fee_percent` - percent; has to be in between MIN_FEE_PERCENT and MAX_FEE_PERCENT
+    /// * `base_fee_percent` - in basis points, 1/100 of a percent; has to be in between MIN_FEE_PERCENT and MAX_FEE_PERCENT
+    /// * `treasury_account_id` - where the fees get withdrawn to; the owner, if it isn't provided
     #[init]
-    pub fn init(_owner_id: Option<AccountId>, base_fee_percent: Option<u128>) -> Self {
+    pub fn init(
+        _owner_id: Option<AccountId>,
+        base_fee_percent: Option<u128>,
+        treasury_account_id: Option<AccountId>,
+    ) -> Self {
         require!(!env::state_exists(), "Already initialized");
         let owner_id = _owner_id.unwrap_or(env::signer_account_id());
+        let treasury_account_id = treasury_account_id.unwrap_or_else(|| owner_id.clone());
 
         let base_fee_percent2 = base_fee_percent.unwrap_or(Self::MIN_FEE_PERCENT);
         require!(
-            (base_fee_percent2 >= Self::MIN_FEE_PERCENT) && (base_fee_percent2 <= Self::MAX_FEE_PERCENT),
+            (Self::MIN_FEE_PERCENT..=Self::MAX_FEE_PERCENT).contains(&base_fee_percent2),
             format!(
                 "base_fee_percent must be between {}..{}",
                 &Self::MIN_FEE_PERCENT,
@@ -62,32 +342,277 @@ impl Escrow {
             )
         );
 
-        let items: TreeMap<EscrowId, EscrowItem> = TreeMap::new(b"t");
+        let items: TreeMap<EscrowId, VersionedEscrowItem> = TreeMap::new(b"t");
+        versioned::write_state_version(STATE_VERSION);
         Self {
             owner_id,
+            proposed_owner_id: None,
+            roles: Roles::new(b"g"),
+            pause_flags: PauseFlags::default(),
             base_fee_percent: base_fee_percent2,
+            fee_limits: FeeLimits::default(),
+            fee_schedule: FeeSchedule::new(b"o", b"v"),
             items,
+            claims: ClaimLedger::new(b"c"),
+            treasury: Treasury::new(treasury_account_id, b"a", b"r"),
+            items_by_funder: LookupMap::new(b"f"),
+            items_by_beneficiary: LookupMap::new(b"b"),
+            items_by_status: LookupMap::new(b"s"),
+            emergency_withdrawal_delay: Self::DEFAULT_EMERGENCY_WITHDRAWAL_DELAY,
+            storage_accounts: StorageAccounts::new(b"m"),
+            last_escrow_id: 0,
+            items_by_external_ref: LookupMap::new(b"x"),
+            index_backfill: Backfill::Done,
+            accepted_tokens: UnorderedSet::new(b"k"),
+            ft_storage_authorized: LookupSet::new(b"p"),
+            refunds: ClaimLedger::new(b"e"),
+        }
+    }
+
+    /// upgrades the state of an older layout, once new code has been deployed;
+    /// the escrows get upgraded one by one, once read
+    ///
+    /// who may call this method
+    ///     * owner
+    ///     * the contract itself
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let state = VersionedEscrow::read();
+        let caller = env::predecessor_account_id();
+        require!(
+            &caller == state.owner_id() || caller == env::current_account_id(),
+            "only owner may call this method"
+        );
+
+        let from_version = state.version();
+        let escrow = Escrow::from(state);
+        versioned::write_state_version(STATE_VERSION);
+        EscrowEvent::StateMigrate {
+            from_version,
+            to_version: STATE_VERSION,
+        }
+        .emit();
+        escrow
+    }
+
+    /// returns the version of the layout of the state
+    pub fn get_state_version(&self) -> u32 {
+        versioned::read_state_version()
+    }
+
+    /// indexes up to `limit` of the escrows stored in the layout deployed first, which `migrate` leaves unindexed;
+    /// to be called until it returns true, as the escrows show up in the lists only once indexed
+    ///
+    /// who may call this method
+    ///     * owner
+    pub fn index_old_escrows(&mut self, limit: Option<u64>) -> bool {
+        require!(
+            self.owner_id == env::predecessor_account_id(),
+            "only owner may call this method"
+        );
+
+        let escrow_ids = self
+            .index_backfill
+            .next_page(&self.items, limit.unwrap_or(Self::DEFAULT_PAGE_LIMIT));
+        for escrow_id in escrow_ids {
+            if let Some(VersionedEscrowItem::V0(escrow_item)) = self.items.get(&escrow_id) {
+                // written back in the latest layout, which indexes it
+                self.save_item(&escrow_item.into());
+            }
+        }
+
+        self.index_backfill.is_done()
+    }
+
+    /// stages new code of the contract, in place of the code staged before, if any;
+    /// it may be deployed once upgrade::MIN_DEPLOY_DELAY has passed, giving the users time to check it
+    ///
+    /// who may call this method
+    ///     * owner
+    pub fn stage_code(&mut self, code: Base64VecU8) -> StagedCode {
+        require!(
+            self.owner_id == env::predecessor_account_id(),
+            "only owner may call this method"
+        );
+
+        let staged_code = upgrade::stage(&code.0);
+        EscrowEvent::CodeStage {
+            hash: staged_code.hash,
+            deployable_at: staged_code.deployable_at,
+        }
+        .emit();
+        staged_code
+    }
+
+    /// removes the staged code, if any
+    ///
+    /// who may call this method
+    ///     * owner
+    pub fn unstage_code(&mut self) -> bool {
+        require!(
+            self.owner_id == env::predecessor_account_id(),
+            "only owner may call this method"
+        );
+
+        let unstaged = upgrade::unstage();
+        if unstaged {
+            EscrowEvent::CodeUnstage {}.emit();
         }
+
+        unstaged
+    }
+
+    /// deploys the staged code, once the delay has passed, and migrates the state to its layout
+    ///
+    /// who may call this method
+    ///     * owner
+    pub fn deploy_staged_code(&mut self) -> Promise {
+        require!(
+            self.owner_id == env::predecessor_account_id(),
+            "only owner may call this method"
+        );
+
+        let (staged_code, promise) = upgrade::deploy();
+        EscrowEvent::CodeDeploy { hash: staged_code.hash }.emit();
+        promise
+    }
+
+    /// returns the staged code, if any: its sha256 hash, to check it against the source, and when it may be deployed
+    pub fn get_staged_code(&self) -> Option<StagedCode> {
+        upgrade::staged()
     }
 
-    /// returns base_fee as percent
+    /// returns base_fee in basis points
     pub fn get_base_fee_percent(&self) -> Balance {
         self.base_fee_percent
     }
 
+    /// returns the minimum absolute fee of the escrows in NEAR
+    pub fn get_min_fee(&self) -> U128 {
+        U128(self.fee_limits.min_fee)
+    }
+
+    /// returns the maximum absolute fee of the escrows in NEAR; None if there's no maximum
+    pub fn get_max_fee(&self) -> Option<U128> {
+        self.fee_limits.max_fee.map(U128)
+    }
+
+    /// sets the absolute bounds the fee of the escrows in NEAR gets clamped by
+    pub fn set_fee_limits(&mut self, min_fee: U128, max_fee: Option<U128>) {
+        self.require_role(Role::FeeManager);
+
+        self.fee_limits = FeeLimits::new(min_fee.0, max_fee.map(|f| f.0));
+        EscrowEvent::FeeLimitsChange { min_fee, max_fee }.emit();
+    }
+
+    /// returns the fee tiers, by the cumulative volume of the funders in NEAR
+    pub fn get_fee_tiers(&self) -> Vec<FeeTier> {
+        self.fee_schedule.tiers().to_vec()
+    }
+
+    /// replaces the fee tiers; they have to be sorted by `min_volume`, ascending
+    pub fn set_fee_tiers(&mut self, tiers: Vec<FeeTier>) {
+        self.require_role(Role::FeeManager);
+
+        self.fee_schedule.set_tiers(tiers.clone());
+        EscrowEvent::FeeTiersChange { tiers }.emit();
+    }
+
+    /// returns the fee of a funder that overrides the tiers, if any
+    pub fn get_fee_override(&self, account_id: AccountId) -> Option<u128> {
+        self.fee_schedule.override_of(&account_id)
+    }
+
+    /// sets the fee of a funder regardless of the tiers, such as a discount or 0 for an exempt one;
+    /// None removes the override
+    pub fn set_fee_override(&mut self, account_id: AccountId, fee_percent: Option<u128>) {
+        self.require_role(Role::FeeManager);
+
+        self.fee_schedule.set_override(&account_id, fee_percent);
+        EscrowEvent::FeeOverrideChange {
+            account_id,
+            fee_percent: fee_percent.map(U128),
+        }
+        .emit();
+    }
+
+    /// returns the cumulative volume in NEAR released from the escrows of a funder
+    pub fn get_volume(&self, account_id: AccountId) -> U128 {
+        U128(self.fee_schedule.volume_of(&account_id))
+    }
+
+    /// returns the fee, in basis points, a new escrow of a funder would get
+    pub fn get_fee_percent_of(&self, account_id: AccountId) -> u128 {
+        self.fee_schedule.fee_bps_of(&account_id, self.base_fee_percent)
+    }
+
     /// returns the Id of the owner
     pub fn get_owner_id(&self) -> AccountId {
         self.owner_id.clone()
     }
 
-    /// set base_fee as percent
-    /// it has to be in between MIN_FEE_PERCENT and MAX_FEE_PERCENT
-    pub fn set_base_fee_percent(&mut self, new_fee: Balance) -> Balance {
+    /// returns the account proposed to become the owner, if any
+    pub fn get_proposed_owner_id(&self) -> Option<AccountId> {
+        self.proposed_owner_id.clone()
+    }
+
+    /// the first step of transferring the ownership;
+    /// the proposed account becomes the owner once it accepts it
+    pub fn propose_owner(&mut self, proposed_owner_id: AccountId) {
+        require!(
+            self.owner_id == env::predecessor_account_id(),
+            "only owner may call this method"
+        );
+        require!(
+            proposed_owner_id != self.owner_id,
+            "the proposed account is the owner already"
+        );
+
+        self.proposed_owner_id = Some(proposed_owner_id.clone());
+        EscrowEvent::OwnershipPropose {
+            owner_id: self.owner_id.clone(),
+            proposed_owner_id,
+        }
+        .emit();
+    }
+
+    /// the second step of transferring the ownership, called by the proposed account
+    pub fn accept_ownership(&mut self) {
+        let caller = env::predecessor_account_id();
+        require!(
+            self.proposed_owner_id.as_ref() == Some(&caller),
+            "only the proposed owner may call this method"
+        );
+
+        let old_owner_id = std::mem::replace(&mut self.owner_id, caller.clone());
+        self.proposed_owner_id = None;
+        EscrowEvent::OwnershipTransfer {
+            old_owner_id,
+            new_owner_id: caller,
+        }
+        .emit();
+    }
+
+    /// withdraws the proposal; the owner stays the same
+    pub fn cancel_ownership_transfer(&mut self) {
         require!(
             self.owner_id == env::predecessor_account_id(),
             "only owner may call this method"
         );
-        let cond = (new_fee >= Self::MIN_FEE_PERCENT) && (new_fee <= Self::MAX_FEE_PERCENT);
+
+        match self.proposed_owner_id.take() {
+            Some(proposed_owner_id) => {
+                EscrowEvent::OwnershipTransferCancel { proposed_owner_id }.emit();
+            }
+            None => env::panic_str("there's no ownership transfer to cancel"),
+        }
+    }
+
+    /// set base_fee in basis points
+    /// it has to be in between MIN_FEE_PERCENT and MAX_FEE_PERCENT
+    pub fn set_base_fee_percent(&mut self, new_fee: Balance) -> Balance {
+        self.require_role(Role::FeeManager);
+        let cond = (Self::MIN_FEE_PERCENT..=Self::MAX_FEE_PERCENT).contains(&new_fee);
         require!(
             cond,
             format!(
@@ -96,156 +621,430 @@ impl Escrow {
                 Self::MAX_FEE_PERCENT
             )
         );
+        EscrowEvent::FeeChange {
+            old_fee_percent: U128(self.base_fee_percent),
+            new_fee_percent: U128(new_fee),
+        }
+        .emit();
         self.base_fee_percent = new_fee;
         self.base_fee_percent
     }
 
-    ///creates and activates a new escrow
-    ///requires a payment
+    ///creates a new escrow and returns its escrow_id, generated by the contract
+    ///if the attached deposit covers agreed_amount, the escrow gets activated at once;
+    ///otherwise it stays New until it's topped up via `deposit`
+    /// * `external_ref` - optional; a reference of the caller's own, see `list_escrows_by_external_ref`
+    /// * `milestones` - optional; if provided, the amounts of them must add up to `agreed_amount`
+    /// * `release_deadline_at` - optional; nanoseconds, must be in the future
+    /// * `arbiter_account_id` - optional; a third party that resolves disputes, if any
+    /// * `beneficiaries` - optional; the shares must add up to 10000 basis points,
+    ///   and beneficiary_account_id must be one of them
+    /// * `approvers`, `approval_threshold` - optional; if provided, a release requires
+    ///   the approvals of at least `approval_threshold` of `approvers`
     #[payable]
+    #[allow(clippy::new_ret_no_self, clippy::too_many_arguments)]
     pub fn new(
         &mut self,
-        escrow_id: EscrowId,
+        external_ref: Option<String>,
         funder_account_id: AccountId,
         beneficiary_account_id: AccountId,
-        agreed_amount: Balance,
-        current_fee_percent: Option<u128>,
-    ) -> Option<EscrowId> {
-        require!(agreed_amount > 0, "agreed_amount must be greater than 0");
+        agreed_amount: U128,
+        milestones: Option<Vec<MilestoneArgs>>,
+        release_deadline_at: Option<u64>,
+        arbiter_account_id: Option<AccountId>,
+        beneficiaries: Option<Vec<BeneficiaryShare>>,
+        approvers: Option<Vec<AccountId>>,
+        approval_threshold: Option<u32>,
+    ) -> EscrowId {
+        let args = NewEscrowArgs {
+            external_ref,
+            funder_account_id,
+            beneficiary_account_id,
+            agreed_amount,
+            milestones,
+            release_deadline_at,
+            arbiter_account_id,
+            beneficiaries,
+            approvers,
+            approval_threshold,
+        };
 
-        let actual_amount: Balance = env::attached_deposit();
+        //the storage of the escrow is paid for out of the storage deposit of the caller, if registered,
+        //otherwise out of the attached deposit, on top of agreed_amount; the escrow always gets
+        //what's been deposited for it, so only a registered caller may fund it partially
+        let caller = env::predecessor_account_id();
+        let attached_deposit = env::attached_deposit();
         require!(
-            actual_amount > 0,
-            format!("expected deposit: {}; actual one: {}", agreed_amount, actual_amount)
-        );
-        require!(
-            agreed_amount == actual_amount,
+            attached_deposit >= agreed_amount.0 || self.storage_accounts.is_registered(&caller),
             format!(
-                "agreed_amount and actual_amount must be equal: {} and {}",
-                agreed_amount, actual_amount
+                "{} must be registered with storage_deposit to fund an escrow partially",
+                caller
             )
         );
 
-        let cond = (self.owner_id == env::predecessor_account_id()) || (funder_account_id == env::predecessor_account_id());
-        require!(cond, "only funder or owner of this escrow may call this method");
+        let initial_storage_usage = env::storage_usage();
+        let initial_amount = attached_deposit.min(agreed_amount.0);
+        let escrow_id = self.create_item(args, caller.clone(), None, initial_amount, Some(caller.clone()));
+        let cost = self
+            .storage_accounts
+            .charge(&caller, attached_deposit, initial_amount, initial_storage_usage);
+        let escrow_item = self.record_storage_cost(&escrow_id, cost);
 
-        //FIXME:
-        //in case of any error, return the funds to the funder
-        //Promise::new(env::predecessor_account_id()).transfer(env::attached_deposit());
-
-        if !self.items.contains_key(&escrow_id) {
-            let new_item = EscrowItem {
-                escrow_id: escrow_id.clone(),
-                agreed_amount,
-                current_amount: agreed_amount,
-                status: Status::Active,
-                funder_account_id,
-                beneficiary_account_id,
-                current_fee_percent: current_fee_percent.unwrap_or(self.base_fee_percent),
-            };
-
-            self.items.insert(&escrow_id.clone(), &new_item);
-            Some(escrow_id)
-        } else {
-            log!("escrow_id '{}' already exists; generate a new one", escrow_id);
-            None
-        }
+        Self::emit_escrow_create(&escrow_item);
+        escrow_id
     }
 
-    /// releases funds to beneficiary:
-    ///     (100 - fee %)    --> to beneficiary
-    ///     (fee %)          --> to owner
+    /// tops up a New escrow; once current_amount reaches agreed_amount, the escrow becomes Active
     ///
     /// who may call this method
     ///     * owner
     ///     * funder
-    pub fn release_deposit(&mut self, escrow_id: EscrowId) {
-        match self.items.get(&escrow_id) {
-            Some(mut escrow_item) => {
-                require!(escrow_item.status == Status::Active, "this escrow isn't active");
-                let authoriz_cond = (self.owner_id == env::predecessor_account_id())
-                    || (escrow_item.funder_account_id == env::predecessor_account_id());
+    #[payable]
+    pub fn deposit(&mut self, escrow_id: EscrowId) -> U128 {
+        U128(self.deposit_item(escrow_id, env::predecessor_account_id(), None, env::attached_deposit()))
+    }
+
+    /// creates or tops up an escrow funded with a NEP-141 token;
+    /// called by the token contract on `ft_transfer_call`, `msg` is a `FtTransferMessage`
+    ///
+    /// the sender of the tokens acts as the caller of `new` or `deposit`;
+    /// only the tokens accepted by the owner are, see `add_accepted_token`, as any contract may pose as a token
+    /// and pass any sender_id
+    pub fn ft_on_transfer(&mut self, sender_id: AccountId, amount: U128, msg: String) -> PromiseOrValue<U128> {
+        let token_account_id = env::predecessor_account_id();
+        require!(
+            self.accepted_tokens.contains(&token_account_id),
+            format!("the token {} isn't accepted", token_account_id)
+        );
+        let message: FtTransferMessage =
+            serde_json::from_str(&msg).unwrap_or_else(|e| env::panic_str(&format!("invalid msg: {}", e)));
+
+        match message {
+            FtTransferMessage::New(args) => {
+                //nothing but the tokens is attached, so the storage is paid for out of the storage deposit,
+                //which the sender has to have let it be, see authorize_ft_storage
                 require!(
-                    authoriz_cond,
-                    "only funder or owner of this escrow may call this method"
+                    self.storage_accounts.is_registered(&sender_id),
+                    format!(
+                        "{} must be registered with storage_deposit to pay for the storage of the escrow",
+                        sender_id
+                    )
                 );
                 require!(
-                    escrow_item.current_amount >= escrow_item.agreed_amount,
+                    self.ft_storage_authorized.contains(&sender_id),
                     format!(
-                        "the amount of the actual deposit ({}) must be equal of greater than the agreed amount ({})",
-                        escrow_item.current_amount, escrow_item.agreed_amount
+                        "{} must call authorize_ft_storage to pay for the storage of the escrow",
+                        sender_id
                     )
                 );
 
-                let amount_for_beneficiary = escrow_item.agreed_amount / Self::HUNDRED_PERCENT
-                    * (Self::HUNDRED_PERCENT - escrow_item.current_fee_percent);
-                let amount_for_owner = escrow_item.agreed_amount - amount_for_beneficiary;
+                let initial_storage_usage = env::storage_usage();
+                let escrow_id = self.create_item(
+                    *args,
+                    sender_id.clone(),
+                    Some(token_account_id),
+                    amount.0,
+                    Some(sender_id.clone()),
+                );
+                let cost = self.storage_accounts.charge(&sender_id, 0, 0, initial_storage_usage);
+                Self::emit_escrow_create(&self.record_storage_cost(&escrow_id, cost));
+            }
+            FtTransferMessage::Deposit { escrow_id } => {
+                self.deposit_item(escrow_id, sender_id, Some(token_account_id), amount.0);
+            }
+        }
+
+        //all the tokens have been taken
+        PromiseOrValue::Value(U128(0))
+    }
+
+    /// lets ft_on_transfer accept a NEP-141 token; returns whether it wasn't accepted already
+    ///
+    /// who may call this method
+    ///     * owner
+    pub fn add_accepted_token(&mut self, token_account_id: AccountId) -> bool {
+        require!(
+            self.owner_id == env::predecessor_account_id(),
+            "only owner may call this method"
+        );
+
+        let added = self.accepted_tokens.insert(&token_account_id);
+        if added {
+            EscrowEvent::TokenAccept { token_account_id }.emit();
+        }
 
-                //due to a potential rounding error,
-                //verify that there'll be enough of the funds
-                let amounts_sum = amount_for_beneficiary + amount_for_owner;
-                let calc_cond = escrow_item.current_amount >= amounts_sum;
-                require!(calc_cond, format!("current_amount ({}) must be equal to or greater than the sum of the amounts to be released ({});", escrow_item.current_amount, amounts_sum));
+        added
+    }
 
-                //send funds to the beneficiary
-                let p1 = Promise::new(escrow_item.beneficiary_account_id.clone()).transfer(amount_for_beneficiary);
-                escrow_item.current_amount -= amount_for_beneficiary;
-                log!(
-                    "releasing '{}' to beneficiary '{}'; escrow_id '{}'",
-                    amount_for_beneficiary,
-                    escrow_item.beneficiary_account_id,
-                    escrow_id
+    /// stops ft_on_transfer accepting a NEP-141 token; the escrows funded with it carry on as usual
+    ///
+    /// who may call this method
+    ///     * owner
+    pub fn remove_accepted_token(&mut self, token_account_id: AccountId) -> bool {
+        require!(
+            self.owner_id == env::predecessor_account_id(),
+            "only owner may call this method"
+        );
+
+        let removed = self.accepted_tokens.remove(&token_account_id);
+        if removed {
+            EscrowEvent::TokenRemove { token_account_id }.emit();
+        }
+
+        removed
+    }
+
+    pub fn get_accepted_tokens(&self) -> Vec<AccountId> {
+        self.accepted_tokens.to_vec()
+    }
+
+    /// lets, or stops letting, the storage deposit of the caller pay for the escrows it creates with ft_transfer_call;
+    /// nothing but the tokens gets attached to those, and it's the token contract that tells who sent them
+    pub fn authorize_ft_storage(&mut self, authorized: bool) {
+        let account_id = env::predecessor_account_id();
+        if authorized {
+            self.ft_storage_authorized.insert(&account_id);
+        } else {
+            self.ft_storage_authorized.remove(&account_id);
+        }
+    }
+
+    pub fn is_ft_storage_authorized(&self, account_id: AccountId) -> bool {
+        self.ft_storage_authorized.contains(&account_id)
+    }
+
+    /// cancels a New escrow, one that hasn't been fully funded, and sends the deposited funds back to the funder
+    ///
+    /// who may call this method
+    ///     * funder
+    pub fn cancel(&mut self, escrow_id: EscrowId) {
+        match self.read_item(&escrow_id) {
+            Some(mut escrow_item) => {
+                require!(escrow_item.status == Status::New, "this escrow isn't new");
+                require!(
+                    escrow_item.funder_account_id == env::predecessor_account_id(),
+                    "only funder may call this method"
                 );
 
-                //send the fees to the owner
-                let p2 = Promise::new(self.owner_id.clone()).transfer(amount_for_owner);
-                p1.then(p2);
-                //FIXME verify that _p1 has returned successfully
-                escrow_item.current_amount -= amount_for_owner;
                 log!(
-                    "sending commission of '{}' ({}%) to owner_id '{}'; escrow_id '{}'",
-                    amount_for_owner,
-                    escrow_item.current_fee_percent,
-                    self.owner_id,
+                    "canceling, withdrawing '{}' to funder '{}'; escrow_id '{}'",
+                    escrow_item.current_amount,
+                    escrow_item.funder_account_id,
                     escrow_id
                 );
+                if escrow_item.current_amount > 0 {
+                    self.refund_to_funder(&mut escrow_item);
+                }
 
-                escrow_item.status = Status::PayedOff;
+                escrow_item.status = Status::Canceled;
+                self.save_item(&escrow_item);
             }
             None => {
-                //FIXME return None or Error
                 log!("escrow_id '{}' not found", escrow_id)
             }
         }
     }
 
-    /// reimburse the funder the funds
-    /// who may call this method:
+    /// releases funds to beneficiary:
+    ///     (100 - fee %)    --> to beneficiary
+    ///     (fee %)          --> to owner
+    ///
+    /// in an escrow with milestones, all the pending milestones get released at once
+    ///
+    /// who may call this method
     ///     * owner
-    ///     * beneficiary
-    pub fn reimburse_funder(&self, escrow_id: EscrowId) {
-        match self.items.get(&escrow_id) {
+    ///     * funder
+    pub fn release_deposit(&mut self, escrow_id: EscrowId) {
+        self.pause_flags.require_not_paused(Some(Feature::Payouts));
+        match self.read_item(&escrow_id) {
             Some(mut escrow_item) => {
                 require!(escrow_item.status == Status::Active, "this escrow isn't active");
-
-                let cond = (self.owner_id == env::predecessor_account_id())
-                    || (escrow_item.beneficiary_account_id == env::predecessor_account_id());
-                require!(cond, "only beneficiary or owner may call this method");
-
-                //verify that there'll be enough of the funds
-                let calc_cond = escrow_item.current_amount >= escrow_item.agreed_amount;
+                let authoriz_cond = (self.owner_id == env::predecessor_account_id())
+                    || (escrow_item.funder_account_id == env::predecessor_account_id());
                 require!(
-                    calc_cond,
-                    format!(
-                        "current_amount ({}) must be equal to or greater than agreed_amount ({});",
-                        escrow_item.current_amount, escrow_item.agreed_amount
-                    )
+                    authoriz_cond,
+                    "only funder or owner of this escrow may call this method"
                 );
+                Self::require_approvals(&escrow_item);
 
-                let _p1 = Promise::new(escrow_item.funder_account_id.clone()).transfer(escrow_item.agreed_amount);
-                //FIXME verify that _p1 has returned successfully
+                let amount_to_release = if escrow_item.milestones.is_empty() {
+                    require!(
+                        escrow_item.current_amount >= escrow_item.agreed_amount,
+                        format!(
+                            "the amount of the actual deposit ({}) must be equal of greater than the agreed amount ({})",
+                            escrow_item.current_amount, escrow_item.agreed_amount
+                        )
+                    );
+
+                    escrow_item.agreed_amount
+                } else {
+                    let mut pending_sum = 0;
+                    for m in escrow_item.milestones.iter_mut() {
+                        if m.status == MilestoneStatus::Pending {
+                            pending_sum += m.amount;
+                            m.status = MilestoneStatus::Released;
+                        }
+                    }
+
+                    pending_sum
+                };
+
+                self.release_to_beneficiary(&mut escrow_item, amount_to_release);
+                escrow_item.status = Status::PayedOff;
+                self.save_item(&escrow_item);
+            }
+            None => {
+                //FIXME return None or Error
+                log!("escrow_id '{}' not found", escrow_id)
+            }
+        }
+    }
+
+    /// releases a single milestone to beneficiary, the fee is taken off the milestone's amount;
+    /// once the last milestone has been released, the escrow becomes PayedOff
+    ///
+    /// who may call this method
+    ///     * owner
+    ///     * funder
+    pub fn release_milestone(&mut self, escrow_id: EscrowId, milestone_name: String) {
+        self.pause_flags.require_not_paused(Some(Feature::Payouts));
+        match self.read_item(&escrow_id) {
+            Some(mut escrow_item) => {
+                require!(escrow_item.status == Status::Active, "this escrow isn't active");
+                let authoriz_cond = (self.owner_id == env::predecessor_account_id())
+                    || (escrow_item.funder_account_id == env::predecessor_account_id());
+                require!(
+                    authoriz_cond,
+                    "only funder or owner of this escrow may call this method"
+                );
+                Self::require_approvals(&escrow_item);
+
+                let milestone = escrow_item
+                    .milestones
+                    .iter_mut()
+                    .find(|m| m.name == milestone_name)
+                    .unwrap_or_else(|| env::panic_str(&format!("milestone '{}' not found", milestone_name)));
+                require!(
+                    milestone.status == MilestoneStatus::Pending,
+                    format!("milestone '{}' has already been released", milestone_name)
+                );
+
+                milestone.status = MilestoneStatus::Released;
+                let amount_to_release = milestone.amount;
+                log!(
+                    "releasing milestone '{}' of '{}'; escrow_id '{}'",
+                    milestone_name,
+                    amount_to_release,
+                    escrow_id
+                );
+
+                self.release_to_beneficiary(&mut escrow_item, amount_to_release);
+                //the next milestone requires the approvals anew
+                escrow_item.approvals.clear();
+                if escrow_item
+                    .milestones
+                    .iter()
+                    .all(|m| m.status == MilestoneStatus::Released)
+                {
+                    escrow_item.status = Status::PayedOff;
+                }
+
+                self.save_item(&escrow_item);
+            }
+            None => {
+                log!("escrow_id '{}' not found", escrow_id)
+            }
+        }
+    }
+
+    /// approves releasing an Active escrow, or its next milestone, or the split of a Disputed one
+    ///
+    /// who may call this method
+    ///     * approvers
+    pub fn approve_release(&mut self, escrow_id: EscrowId) -> u32 {
+        self.pause_flags.require_not_paused(None);
+        match self.read_item(&escrow_id) {
+            Some(mut escrow_item) => {
+                require!(
+                    matches!(escrow_item.status, Status::Active | Status::Disputed),
+                    "this escrow is neither active nor disputed"
+                );
+                let caller = env::predecessor_account_id();
+                require!(
+                    escrow_item.approvers.contains(&caller),
+                    "only approvers of this escrow may call this method"
+                );
+                require!(
+                    !escrow_item.approvals.contains(&caller),
+                    "the release has already been approved by the caller"
+                );
+
+                escrow_item.approvals.push(caller.clone());
+                log!(
+                    "release approved by '{}', {} of {}; escrow_id '{}'",
+                    caller,
+                    escrow_item.approvals.len(),
+                    escrow_item.approval_threshold,
+                    escrow_id
+                );
+
+                self.save_item(&escrow_item);
+                escrow_item.approvals.len() as u32
+            }
+            None => env::panic_str(&format!("escrow_id '{}' not found", escrow_id)),
+        }
+    }
+
+    /// revokes the caller's approval of releasing an Active escrow
+    ///
+    /// who may call this method
+    ///     * approvers
+    pub fn revoke_approval(&mut self, escrow_id: EscrowId) -> u32 {
+        self.pause_flags.require_not_paused(None);
+        match self.read_item(&escrow_id) {
+            Some(mut escrow_item) => {
+                require!(
+                    matches!(escrow_item.status, Status::Active | Status::Disputed),
+                    "this escrow is neither active nor disputed"
+                );
+                let caller = env::predecessor_account_id();
+                require!(
+                    escrow_item.approvals.contains(&caller),
+                    "the release hasn't been approved by the caller"
+                );
+
+                escrow_item.approvals.retain(|a| *a != caller);
+                log!(
+                    "approval revoked by '{}', {} of {}; escrow_id '{}'",
+                    caller,
+                    escrow_item.approvals.len(),
+                    escrow_item.approval_threshold,
+                    escrow_id
+                );
+
+                self.save_item(&escrow_item);
+                escrow_item.approvals.len() as u32
+            }
+            None => env::panic_str(&format!("escrow_id '{}' not found", escrow_id)),
+        }
+    }
+
+    /// reimburse the funder the funds that are left in an escrow
+    /// who may call this method:
+    ///     * owner
+    ///     * beneficiary
+    pub fn reimburse_funder(&mut self, escrow_id: EscrowId) {
+        match self.read_item(&escrow_id) {
+            Some(mut escrow_item) => {
+                require!(escrow_item.status == Status::Active, "this escrow isn't active");
+
+                let cond = (self.owner_id == env::predecessor_account_id())
+                    || (escrow_item.beneficiary_account_id == env::predecessor_account_id());
+                require!(cond, "only beneficiary or owner may call this method");
+
+                self.refund_to_funder(&mut escrow_item);
                 escrow_item.status = Status::Reimbursed;
-                escrow_item.current_amount = 0;
+                self.save_item(&escrow_item);
             }
             None => {
                 //FIXME return None or Error
@@ -255,16 +1054,32 @@ impl Escrow {
         }
     }
 
+    /// removes an escrow that doesn't hold funds anymore
+    ///
+    /// who may call this method
+    ///     * operator
     pub fn remove_item(&mut self, escrow_id: EscrowId) {
-        match self.items.get(&escrow_id) {
+        self.pause_flags.require_not_paused(None);
+        match self.read_item(&escrow_id) {
             Some(escrow_item) => {
                 require!(
-                    escrow_item.status != Status::Active,
-                    format!("escrow id {} is active, therefore it may not be removed", escrow_id)
+                    !matches!(escrow_item.status, Status::New | Status::Active | Status::Disputed),
+                    format!(
+                        "escrow id {} still holds funds, therefore it may not be removed",
+                        escrow_id
+                    )
                 );
 
-                require!(self.owner_id == env::predecessor_account_id(), "owner's only method");
-                self.items.remove(&escrow_id);
+                self.require_role(Role::Operator);
+                let initial_storage_usage = env::storage_usage();
+                self.delete_item(&escrow_item);
+
+                //the freed storage goes back to the one who has paid for it, though no more than he's paid:
+                //the contract has paid for what the escrow has taken since
+                if let Some(payer) = &escrow_item.storage_payer_id {
+                    let refund = storage::storage_refund_since(initial_storage_usage).min(escrow_item.storage_cost);
+                    self.storage_accounts.release(payer, refund);
+                }
             }
             None => {
                 log!("escrow_id '{}' not found", escrow_id)
@@ -272,21 +1087,274 @@ impl Escrow {
         }
     }
 
-    pub fn emergency_withdraw(&mut self, escrow_id: EscrowId) {
-        match self.items.get(&escrow_id) {
+    /// deposits the attached funds for the storage of `account_id`, the caller by default, registering it if need be;
+    /// if `registration_only`, just what the registration costs gets deposited and the rest refunded
+    #[payable]
+    pub fn storage_deposit(
+        &mut self,
+        account_id: Option<AccountId>,
+        registration_only: Option<bool>,
+    ) -> StorageBalance {
+        let account_id = account_id.unwrap_or_else(env::predecessor_account_id);
+        let (balance, refund) =
+            self.storage_accounts
+                .deposit(&account_id, env::attached_deposit(), registration_only.unwrap_or(false));
+
+        storage::refund(&env::predecessor_account_id(), refund);
+        balance
+    }
+
+    /// withdraws `amount` of the available storage deposit of the caller; all of it, if amount isn't provided
+    #[payable]
+    pub fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let (balance, amount) = self.storage_accounts.withdraw(&account_id, amount.map(|a| a.0));
+        storage::refund(&account_id, amount);
+        balance
+    }
+
+    /// unregisters the caller and refunds its whole storage deposit, once its escrows have been removed;
+    /// returns false if the caller isn't registered
+    #[payable]
+    pub fn storage_unregister(&mut self, force: Option<bool>) -> bool {
+        assert_one_yocto();
+        require!(
+            !force.unwrap_or(false),
+            "force isn't supported; the escrows have to be removed first"
+        );
+
+        let account_id = env::predecessor_account_id();
+        match self.storage_accounts.unregister(&account_id) {
+            Some(total) => {
+                self.ft_storage_authorized.remove(&account_id);
+                storage::refund(&account_id, total);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
+        self.storage_accounts.balance_of(&account_id)
+    }
+
+    pub fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+        StorageAccounts::bounds()
+    }
+
+    /// returns the delay, in nanoseconds, between announcing an emergency withdrawal and executing it
+    pub fn get_emergency_withdrawal_delay(&self) -> u64 {
+        self.emergency_withdrawal_delay
+    }
+
+    /// sets the delay, in nanoseconds, of the emergency withdrawals announced from now on, 1 day at least;
+    /// the ones announced already keep their `executable_at`
+    ///
+    /// who may call this method
+    ///     * owner
+    pub fn set_emergency_withdrawal_delay(&mut self, delay: u64) {
+        require!(self.owner_id == env::predecessor_account_id(), "owner's only method");
+        require!(
+            delay >= Self::MIN_EMERGENCY_WITHDRAWAL_DELAY,
+            format!(
+                "the delay must be at least {} nanoseconds",
+                Self::MIN_EMERGENCY_WITHDRAWAL_DELAY
+            )
+        );
+        EscrowEvent::EmergencyWithdrawalDelayChange {
+            old_delay: self.emergency_withdrawal_delay,
+            new_delay: delay,
+        }
+        .emit();
+        self.emergency_withdrawal_delay = delay;
+    }
+
+    /// the first step of an emergency withdrawal of the funds of an escrow to one of its parties;
+    /// the parties see the announcement in the escrow and may settle it before it gets executed
+    ///
+    /// who may call this method
+    ///     * owner
+    pub fn announce_emergency_withdrawal(&mut self, escrow_id: EscrowId, reason: String, account_id: AccountId) {
+        match self.read_item(&escrow_id) {
+            Some(mut escrow_item) => {
+                require!(self.owner_id == env::predecessor_account_id(), "owner's only method");
+                require!(!reason.is_empty(), "reason must not be empty");
+                require!(
+                    escrow_item.emergency_withdrawal.is_none(),
+                    "an emergency withdrawal has been announced already"
+                );
+                require!(
+                    matches!(escrow_item.status, Status::New | Status::Active),
+                    "this escrow doesn't hold funds or is disputed"
+                );
+                require!(
+                    account_id == escrow_item.funder_account_id
+                        || Self::beneficiary_ids(&escrow_item).contains(&account_id),
+                    "the funds may be withdrawn only to funder or a beneficiary"
+                );
+
+                let now = env::block_timestamp();
+                let executable_at = now + self.emergency_withdrawal_delay;
+                EscrowEvent::EmergencyWithdrawalAnnounce {
+                    escrow_id: escrow_id.clone(),
+                    reason: reason.clone(),
+                    account_id: account_id.clone(),
+                    executable_at,
+                }
+                .emit();
+
+                escrow_item.emergency_withdrawal = Some(EmergencyWithdrawal {
+                    reason,
+                    account_id,
+                    announced_at: now,
+                    executable_at,
+                });
+                self.save_item(&escrow_item);
+            }
+            None => env::panic_str(&format!("escrow_id '{}' not found", escrow_id)),
+        }
+    }
+
+    /// who may call this method
+    ///     * owner
+    pub fn cancel_emergency_withdrawal(&mut self, escrow_id: EscrowId) {
+        match self.read_item(&escrow_id) {
+            Some(mut escrow_item) => {
+                require!(self.owner_id == env::predecessor_account_id(), "owner's only method");
+                require!(
+                    escrow_item.emergency_withdrawal.take().is_some(),
+                    "no emergency withdrawal has been announced"
+                );
+
+                EscrowEvent::EmergencyWithdrawalCancel {
+                    escrow_id: escrow_id.clone(),
+                }
+                .emit();
+                self.save_item(&escrow_item);
+            }
+            None => env::panic_str(&format!("escrow_id '{}' not found", escrow_id)),
+        }
+    }
+
+    /// the second step of an emergency withdrawal; credits all the funds left in an escrow to the party
+    /// chosen in the announcement: back to the funder or, as a release, to the beneficiaries by their shares,
+    /// taking the fee off
+    ///
+    /// who may call this method
+    ///     * owner
+    pub fn execute_emergency_withdrawal(&mut self, escrow_id: EscrowId) {
+        match self.read_item(&escrow_id) {
             Some(mut escrow_item) => {
                 require!(self.owner_id == env::predecessor_account_id(), "owner's only method");
-                let _p1 = Promise::new(self.owner_id.clone()).transfer(escrow_item.current_amount);
+                let withdrawal = match escrow_item.emergency_withdrawal.take() {
+                    Some(w) => w,
+                    None => env::panic_str("no emergency withdrawal has been announced"),
+                };
+                let now = env::block_timestamp();
+                require!(
+                    now >= withdrawal.executable_at,
+                    format!(
+                        "the emergency withdrawal may be executed at {} or later",
+                        withdrawal.executable_at
+                    )
+                );
+                //a disputed escrow is up to the arbiter
+                require!(
+                    matches!(escrow_item.status, Status::New | Status::Active) && escrow_item.current_amount > 0,
+                    "this escrow doesn't hold funds anymore or is disputed"
+                );
+
+                let amount = escrow_item.current_amount;
+                if withdrawal.account_id == escrow_item.funder_account_id {
+                    self.refund_to_funder(&mut escrow_item);
+                    escrow_item.status = Status::Reimbursed;
+                } else {
+                    //the withdrawal to the beneficiaries is a release, hence it requires the approvals, if any
+                    Self::require_approvals(&escrow_item);
+                    for m in escrow_item.milestones.iter_mut() {
+                        if m.status == MilestoneStatus::Pending {
+                            m.status = MilestoneStatus::Released;
+                        }
+                    }
+
+                    self.release_to_beneficiary(&mut escrow_item, amount);
+                    escrow_item.status = Status::PayedOff;
+                }
+
+                EscrowEvent::EscrowEmergencyWithdraw {
+                    escrow_id: escrow_id.clone(),
+                    account_id: withdrawal.account_id,
+                    amount: U128(amount),
+                    reason: withdrawal.reason,
+                    token_account_id: escrow_item.token_account_id.clone(),
+                }
+                .emit();
+                self.save_item(&escrow_item);
+            }
+            None => env::panic_str(&format!("escrow_id '{}' not found", escrow_id)),
+        }
+    }
+
+    /// lets the funder take back the funds of an Active escrow
+    /// whose release deadline has passed, without waiting for the beneficiary or owner
+    ///
+    /// who may call this method:
+    ///     * funder
+    pub fn reclaim_expired(&mut self, escrow_id: EscrowId) {
+        match self.read_item(&escrow_id) {
+            Some(mut escrow_item) => {
+                require!(escrow_item.status == Status::Active, "this escrow isn't active");
+                require!(
+                    escrow_item.funder_account_id == env::predecessor_account_id(),
+                    "only funder may call this method"
+                );
+
+                let now = env::block_timestamp();
+                match escrow_item.release_deadline_at {
+                    Some(deadline) => require!(
+                        now > deadline,
+                        format!("the release deadline ({}) hasn't passed yet ({})", deadline, now)
+                    ),
+                    None => env::panic_str("this escrow has no release deadline"),
+                }
+
                 log!(
-                    "emergency withdrawal from an escrow by owner; amount: {}, escrow_id: {}, owner_id: {}",
+                    "release deadline has passed, reclaiming '{}' to funder '{}'; escrow_id '{}'",
                     escrow_item.current_amount,
-                    escrow_id,
-                    self.owner_id
+                    escrow_item.funder_account_id,
+                    escrow_id
                 );
+                self.refund_to_funder(&mut escrow_item);
+                escrow_item.status = Status::Reimbursed;
+                self.save_item(&escrow_item);
+            }
+            None => {
+                log!("escrow_id '{}' not found", escrow_id)
+            }
+        }
+    }
 
-                //FIXME
-                //verify that the _p1 has finished successfully
-                escrow_item.current_amount = 0;
+    /// freezes an Active escrow that has an arbiter;
+    /// neither release_deposit nor reimburse_funder may be called until the arbiter resolves it
+    ///
+    /// who may call this method:
+    ///     * funder
+    ///     * beneficiary or any of the beneficiaries with a share
+    pub fn raise_dispute(&mut self, escrow_id: EscrowId) {
+        self.pause_flags.require_not_paused(None);
+        match self.read_item(&escrow_id) {
+            Some(mut escrow_item) => {
+                require!(escrow_item.status == Status::Active, "this escrow isn't active");
+                require!(escrow_item.arbiter_account_id.is_some(), "this escrow has no arbiter");
+
+                let caller = env::predecessor_account_id();
+                let cond = (escrow_item.funder_account_id == caller) || Self::is_beneficiary(&escrow_item, &caller);
+                require!(cond, "only funder or beneficiary may call this method");
+
+                escrow_item.status = Status::Disputed;
+                log!("dispute raised by '{}'; escrow_id '{}'", caller, escrow_id);
+                self.save_item(&escrow_item);
             }
             None => {
                 log!("escrow_id '{}' not found", escrow_id)
@@ -294,41 +1362,2338 @@ impl Escrow {
         }
     }
 
-    /// returns the balance of an EscrowItem
-    pub fn get_balance(&self, escrow_id: EscrowId) -> Option<Balance> {
-        match self.items.get(&escrow_id) {
-            Some(item) => {
-                require!(item.agreed_amount == item.current_amount);
-                Some(item.agreed_amount)
+    /// resolves a dispute by splitting the funds that are left in an escrow:
+    ///     `amount_for_beneficiary` minus the fee  --> to beneficiary
+    ///     the fee                                 --> to owner
+    ///     the rest                                --> to funder
+    ///
+    /// unless `amount_for_beneficiary` is 0, it requires the approvals, if any
+    ///
+    /// who may call this method:
+    ///     * arbiter
+    ///     * operator
+    pub fn resolve_dispute(&mut self, escrow_id: EscrowId, amount_for_beneficiary: U128) {
+        self.pause_flags.require_not_paused(Some(Feature::Payouts));
+        let amount_for_beneficiary = amount_for_beneficiary.0;
+        match self.read_item(&escrow_id) {
+            Some(mut escrow_item) => {
+                require!(escrow_item.status == Status::Disputed, "this escrow isn't disputed");
+                let caller = env::predecessor_account_id();
+                require!(
+                    escrow_item.arbiter_account_id.as_ref() == Some(&caller) || self.has_role(caller, Role::Operator),
+                    "only arbiter or operator may call this method"
+                );
+                require!(
+                    amount_for_beneficiary <= escrow_item.current_amount,
+                    format!(
+                        "amount_for_beneficiary ({}) must be equal to or less than current_amount ({})",
+                        amount_for_beneficiary, escrow_item.current_amount
+                    )
+                );
+
+                self.split_funds(&mut escrow_item, amount_for_beneficiary);
+                escrow_item.status = Status::Resolved;
+                self.save_item(&escrow_item);
+            }
+            None => {
+                log!("escrow_id '{}' not found", escrow_id)
             }
-            None => None,
         }
     }
-}
 
-#[cfg(not(target_arch = "wasm32"))]
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use near_sdk::test_utils::{accounts, VMContextBuilder};
-    use near_sdk::{testing_env, MockedBlockchain};
+    /// settles an Active escrow by splitting the funds that are left in it:
+    ///     `amount_for_beneficiary` minus the fee  --> to beneficiary
+    ///     the fee                                 --> to owner
+    ///     the rest                                --> to funder
+    ///
+    /// unless `amount_for_beneficiary` is 0, it requires the approvals, if any, the owner's settlement included
+    ///
+    /// when called by funder or beneficiary, it records a proposal;
+    /// the escrow gets settled once the other side calls it with the same amount:
+    /// the funder or any of the beneficiaries, those with a share included
+    ///
+    /// who may call this method:
+    ///     * owner; settles at once
+    ///     * funder
+    ///     * beneficiary or any of the beneficiaries with a share
+    pub fn settle(&mut self, escrow_id: EscrowId, amount_for_beneficiary: U128) {
+        self.pause_flags.require_not_paused(Some(Feature::Payouts));
+        let amount_for_beneficiary = amount_for_beneficiary.0;
+        match self.read_item(&escrow_id) {
+            Some(mut escrow_item) => {
+                require!(escrow_item.status == Status::Active, "this escrow isn't active");
 
-    fn get_context(is_view: bool) -> VMContextBuilder {
-        let mut builder = VMContextBuilder::new();
-        let account0: AccountId = "0.near".parse().unwrap();
-        builder
-            .current_account_id(account0)
-            .signer_account_id(accounts(0))
-            .predecessor_account_id(accounts(0))
-            .is_view(is_view);
+                let caller = env::predecessor_account_id();
+                let is_party = (escrow_item.funder_account_id == caller) || Self::is_beneficiary(&escrow_item, &caller);
+                require!(
+                    is_party || (self.owner_id == caller),
+                    "only funder, beneficiary or owner may call this method"
+                );
+                require!(
+                    amount_for_beneficiary <= escrow_item.current_amount,
+                    format!(
+                        "amount_for_beneficiary ({}) must be equal to or less than current_amount ({})",
+                        amount_for_beneficiary, escrow_item.current_amount
+                    )
+                );
 
-        builder
+                let agreed = match &escrow_item.settlement_proposal {
+                    _ if self.owner_id == caller => true,
+                    //accepted by the other side: the funder or any of the beneficiaries
+                    Some(p) => {
+                        (p.proposed_by == escrow_item.funder_account_id) != (caller == escrow_item.funder_account_id)
+                            && p.amount_for_beneficiary == amount_for_beneficiary
+                    }
+                    None => false,
+                };
+
+                if agreed {
+                    log!(
+                        "settling '{}' to beneficiary; escrow_id '{}'",
+                        amount_for_beneficiary,
+                        escrow_id
+                    );
+                    self.split_funds(&mut escrow_item, amount_for_beneficiary);
+                    escrow_item.settlement_proposal = None;
+                    escrow_item.status = Status::Settled;
+                } else {
+                    log!(
+                        "'{}' proposes settling '{}' to beneficiary; escrow_id '{}'",
+                        caller,
+                        amount_for_beneficiary,
+                        escrow_id
+                    );
+                    escrow_item.settlement_proposal = Some(SettlementProposal {
+                        proposed_by: caller,
+                        amount_for_beneficiary,
+                    });
+                }
+
+                self.save_item(&escrow_item);
+            }
+            None => {
+                log!("escrow_id '{}' not found", escrow_id)
+            }
+        }
     }
 
-    #[test]
-    fn test_init_contract() {
-        let context = get_context(false);
-        //TODO
-        assert_eq!(true, true);
+    /// withdraws the funds credited to the caller by the payouts of the escrows:
+    /// `amount`, or all of them if it isn't provided, of NEAR or, if `token_account_id` is provided, of the token
+    ///
+    /// if the transfer fails, the funds get credited back
+    #[payable]
+    pub fn withdraw(&mut self, amount: Option<U128>, token_account_id: Option<AccountId>) -> Promise {
+        assert_one_yocto();
+        self.pause_flags.require_not_paused(Some(Feature::Payouts));
+        let account_id = env::predecessor_account_id();
+        let amount = self.claims.debit(&account_id, &token_account_id, amount.map(|a| a.0));
+        EscrowEvent::Withdraw {
+            account_id: account_id.clone(),
+            amount: U128(amount),
+            token_account_id: token_account_id.clone(),
+        }
+        .emit();
+
+        self.transfer(&token_account_id, account_id.clone(), amount).then(
+            Self::ext(env::current_account_id())
+                .with_static_gas(GAS_FOR_WITHDRAW_CALLBACK)
+                .on_withdraw(account_id, token_account_id, U128(amount)),
+        )
+    }
+
+    /// callback of `withdraw`; if the transfer has failed, the funds get credited back
+    #[private]
+    pub fn on_withdraw(&mut self, account_id: AccountId, token_account_id: Option<AccountId>, amount: U128) -> bool {
+        if let PromiseResult::Successful(_) = env::promise_result(0) {
+            return true;
+        }
+
+        self.claims.credit(&account_id, &token_account_id, amount.0);
+        EscrowEvent::WithdrawFail {
+            account_id,
+            amount,
+            token_account_id,
+        }
+        .emit();
+        false
+    }
+
+    /// withdraws the funds refunded to the caller as the funder of the escrows:
+    /// `amount`, or all of them if it isn't provided, of NEAR or, if `token_account_id` is provided, of the token;
+    /// unlike `withdraw`, it keeps working while the contract is paused
+    ///
+    /// if the transfer fails, the funds get credited back
+    #[payable]
+    pub fn withdraw_refund(&mut self, amount: Option<U128>, token_account_id: Option<AccountId>) -> Promise {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let amount = self.refunds.debit(&account_id, &token_account_id, amount.map(|a| a.0));
+        EscrowEvent::RefundWithdraw {
+            account_id: account_id.clone(),
+            amount: U128(amount),
+            token_account_id: token_account_id.clone(),
+        }
+        .emit();
+
+        self.transfer(&token_account_id, account_id.clone(), amount).then(
+            Self::ext(env::current_account_id())
+                .with_static_gas(GAS_FOR_WITHDRAW_CALLBACK)
+                .on_withdraw_refund(account_id, token_account_id, U128(amount)),
+        )
+    }
+
+    /// callback of `withdraw_refund`; if the transfer has failed, the funds get credited back
+    #[private]
+    pub fn on_withdraw_refund(
+        &mut self,
+        account_id: AccountId,
+        token_account_id: Option<AccountId>,
+        amount: U128,
+    ) -> bool {
+        if let PromiseResult::Successful(_) = env::promise_result(0) {
+            return true;
+        }
+
+        self.refunds.credit(&account_id, &token_account_id, amount.0);
+        EscrowEvent::RefundWithdrawFail {
+            account_id,
+            amount,
+            token_account_id,
+        }
+        .emit();
+        false
+    }
+
+    /// grants a role to an account; the owner has every role implicitly
+    ///
+    /// who may call this method
+    ///     * owner
+    ///     * super admin, except for the super admin role
+    pub fn grant_role(&mut self, account_id: AccountId, role: Role) -> bool {
+        let caller = env::predecessor_account_id();
+        let granted = self.roles.grant(&self.owner_id, &caller, &account_id, role);
+        if granted {
+            EscrowEvent::RoleGrant {
+                account_id,
+                role,
+                granted_by: caller,
+            }
+            .emit();
+        }
+
+        granted
+    }
+
+    /// revokes a role from an account; who may call this method, the same as of `grant_role`
+    pub fn revoke_role(&mut self, account_id: AccountId, role: Role) -> bool {
+        let caller = env::predecessor_account_id();
+        let revoked = self.roles.revoke(&self.owner_id, &caller, &account_id, role);
+        if revoked {
+            EscrowEvent::RoleRevoke {
+                account_id,
+                role,
+                revoked_by: caller,
+            }
+            .emit();
+        }
+
+        revoked
+    }
+
+    pub fn has_role(&self, account_id: AccountId, role: Role) -> bool {
+        self.roles.has_role(&self.owner_id, &account_id, role)
+    }
+
+    /// pauses `feature` or, if it's None, the whole contract;
+    /// pausing the payouts stops the withdrawals of the credited funds as well, while the refunds,
+    /// and the withdrawals of the refunded funds, keep working
+    pub fn pause(&mut self, feature: Option<Feature>) -> bool {
+        self.set_paused(feature, true)
+    }
+
+    pub fn unpause(&mut self, feature: Option<Feature>) -> bool {
+        self.set_paused(feature, false)
+    }
+
+    /// whether `feature` or, if it's None, the whole contract is paused
+    pub fn is_paused(&self, feature: Option<Feature>) -> bool {
+        self.pause_flags.is_paused(feature)
+    }
+
+    pub fn get_paused_features(&self) -> Vec<Feature> {
+        self.pause_flags.paused_features()
+    }
+
+    /// returns the account the fees get withdrawn to
+    pub fn get_treasury_account_id(&self) -> AccountId {
+        self.treasury.account_id.clone()
+    }
+
+    pub fn set_treasury_account_id(&mut self, treasury_account_id: AccountId) {
+        require!(
+            self.owner_id == env::predecessor_account_id(),
+            "only owner may call this method"
+        );
+
+        self.treasury.account_id = treasury_account_id.clone();
+        EscrowEvent::TreasuryChange { treasury_account_id }.emit();
+    }
+
+    /// returns the fees, of NEAR or of the token, that haven't been withdrawn yet
+    pub fn get_accrued_fees(&self, token_account_id: Option<AccountId>) -> U128 {
+        U128(self.treasury.accrued_of(&token_account_id))
+    }
+
+    /// returns all the fees, of NEAR or of the token, collected over time, the withdrawn ones included
+    pub fn get_total_accrued_fees(&self, token_account_id: Option<AccountId>) -> U128 {
+        U128(self.treasury.total_accrued_of(&token_account_id))
+    }
+
+    /// withdraws `amount`, or all if it isn't provided, of the accrued fees to the treasury;
+    /// if the transfer fails, the fees get restored
+    ///
+    /// who may call this method
+    ///     * owner
+    ///     * treasury
+    #[payable]
+    pub fn withdraw_fees(&mut self, amount: Option<U128>, token_account_id: Option<AccountId>) -> Promise {
+        assert_one_yocto();
+        self.pause_flags.require_not_paused(Some(Feature::Payouts));
+        let caller = env::predecessor_account_id();
+        require!(
+            caller == self.owner_id || caller == self.treasury.account_id,
+            "only owner or treasury may call this method"
+        );
+
+        let amount = self.treasury.take(&token_account_id, amount.map(|a| a.0));
+        let treasury_account_id = self.treasury.account_id.clone();
+        EscrowEvent::FeesWithdraw {
+            treasury_account_id: treasury_account_id.clone(),
+            amount: U128(amount),
+            token_account_id: token_account_id.clone(),
+        }
+        .emit();
+
+        self.transfer(&token_account_id, treasury_account_id, amount).then(
+            Self::ext(env::current_account_id())
+                .with_static_gas(GAS_FOR_WITHDRAW_CALLBACK)
+                .on_withdraw_fees(token_account_id, U128(amount)),
+        )
+    }
+
+    /// callback of `withdraw_fees`; if the transfer has failed, the fees get restored
+    #[private]
+    pub fn on_withdraw_fees(&mut self, token_account_id: Option<AccountId>, amount: U128) -> bool {
+        if let PromiseResult::Successful(_) = env::promise_result(0) {
+            return true;
+        }
+
+        self.treasury.restore(&token_account_id, amount.0);
+        EscrowEvent::FeesWithdrawFail {
+            amount,
+            token_account_id,
+        }
+        .emit();
+        false
+    }
+
+    /// returns the funds an account may withdraw, of NEAR or, if `token_account_id` is provided, of the token
+    pub fn get_claimable_balance(&self, account_id: AccountId, token_account_id: Option<AccountId>) -> U128 {
+        U128(self.claims.balance_of(&account_id, &token_account_id))
+    }
+
+    /// returns the funds refunded to an account as the funder of the escrows, see `withdraw_refund`
+    pub fn get_refundable_balance(&self, account_id: AccountId, token_account_id: Option<AccountId>) -> U128 {
+        U128(self.refunds.balance_of(&account_id, &token_account_id))
+    }
+
+    /// returns an escrow
+    pub fn get_item(&self, escrow_id: EscrowId) -> Option<EscrowView> {
+        self.read_item(&escrow_id).map(EscrowView::from)
+    }
+
+    /// returns the escrows of a funder, `limit` of them starting from `from_index`
+    pub fn list_escrows_by_funder(
+        &self,
+        funder_account_id: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<EscrowView> {
+        self.list_escrows(&self.items_by_funder, &funder_account_id, from_index, limit)
+    }
+
+    /// returns the escrows of a beneficiary, including those he has a share of,
+    /// `limit` of them starting from `from_index`
+    pub fn list_escrows_by_beneficiary(
+        &self,
+        beneficiary_account_id: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<EscrowView> {
+        self.list_escrows(&self.items_by_beneficiary, &beneficiary_account_id, from_index, limit)
+    }
+
+    /// returns the escrows in a status, `limit` of them starting from `from_index`
+    pub fn list_escrows_by_status(
+        &self,
+        status: Status,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<EscrowView> {
+        self.list_escrows(&self.items_by_status, &status, from_index, limit)
+    }
+
+    /// returns the escrows created with an external reference, `limit` of them starting from `from_index`
+    pub fn list_escrows_by_external_ref(
+        &self,
+        external_ref: String,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<EscrowView> {
+        self.list_escrows(&self.items_by_external_ref, &external_ref, from_index, limit)
+    }
+
+    /// returns the balance of an EscrowItem
+    pub fn get_balance(&self, escrow_id: EscrowId) -> Option<U128> {
+        self.read_item(&escrow_id).map(|item| U128(item.current_amount))
+    }
+
+    /// releases `amount_for_beneficiary` to the beneficiary, taking the fee off it,
+    /// and sends the rest back to the funder;
+    /// releasing anything to the beneficiary requires the approvals, if any
+    fn split_funds(&mut self, escrow_item: &mut EscrowItem, amount_for_beneficiary: Balance) {
+        if amount_for_beneficiary > 0 {
+            Self::require_approvals(escrow_item);
+            self.release_to_beneficiary(escrow_item, amount_for_beneficiary);
+        }
+
+        if escrow_item.current_amount > 0 {
+            log!(
+                "reimbursing '{}' to funder '{}'; escrow_id '{}'",
+                escrow_item.current_amount,
+                escrow_item.funder_account_id,
+                escrow_item.escrow_id
+            );
+            self.refund_to_funder(escrow_item);
+        }
+    }
+
+    /// creates a new escrow out of `actual_amount` of NEAR or, if `token_account_id` is provided, of the tokens;
+    /// returns the escrow_id generated for it
+    fn create_item(
+        &mut self,
+        args: NewEscrowArgs,
+        caller: AccountId,
+        token_account_id: Option<AccountId>,
+        actual_amount: Balance,
+        storage_payer_id: Option<AccountId>,
+    ) -> EscrowId {
+        self.pause_flags.require_not_paused(Some(Feature::NewDeals));
+        let NewEscrowArgs {
+            external_ref,
+            funder_account_id,
+            beneficiary_account_id,
+            agreed_amount,
+            milestones,
+            release_deadline_at,
+            arbiter_account_id,
+            beneficiaries,
+            approvers,
+            approval_threshold,
+        } = args;
+        let agreed_amount = agreed_amount.0;
+        require!(agreed_amount > 0, "agreed_amount must be greater than 0");
+        require!(
+            actual_amount <= agreed_amount,
+            format!(
+                "actual_amount must be equal to or less than agreed_amount: {} and {}",
+                actual_amount, agreed_amount
+            )
+        );
+
+        let cond = (self.owner_id == caller) || (funder_account_id == caller);
+        require!(cond, "only funder or owner of this escrow may call this method");
+
+        let milestones: Vec<Milestone> = milestones
+            .unwrap_or_default()
+            .into_iter()
+            .map(|m| {
+                require!(
+                    m.amount.0 > 0,
+                    format!("amount of milestone '{}' must be greater than 0", m.name)
+                );
+                Milestone {
+                    name: m.name,
+                    amount: m.amount.0,
+                    status: MilestoneStatus::Pending,
+                }
+            })
+            .collect();
+
+        if !milestones.is_empty() {
+            let milestones_sum: Balance = milestones.iter().map(|m| m.amount).sum();
+            require!(
+                milestones_sum == agreed_amount,
+                format!(
+                    "the sum of the milestones ({}) must be equal to agreed_amount ({})",
+                    milestones_sum, agreed_amount
+                )
+            );
+
+            for (i, m) in milestones.iter().enumerate() {
+                require!(
+                    !milestones[..i].iter().any(|x| x.name == m.name),
+                    format!("milestone '{}' is duplicated", m.name)
+                );
+            }
+        }
+
+        let beneficiaries = beneficiaries.unwrap_or_default();
+        if !beneficiaries.is_empty() {
+            let shares_sum: u128 = beneficiaries.iter().map(|b| b.share_bps).sum();
+            require!(
+                shares_sum == HUNDRED_PERCENT_BPS,
+                format!(
+                    "the shares of the beneficiaries ({}) must add up to {}",
+                    shares_sum, HUNDRED_PERCENT_BPS
+                )
+            );
+            require!(
+                beneficiaries.iter().any(|b| b.account_id == beneficiary_account_id),
+                "beneficiary_account_id must be one of the beneficiaries"
+            );
+
+            for (i, b) in beneficiaries.iter().enumerate() {
+                require!(
+                    !beneficiaries[..i].iter().any(|x| x.account_id == b.account_id),
+                    format!("beneficiary '{}' is duplicated", b.account_id)
+                );
+            }
+        }
+
+        let approvers = approvers.unwrap_or_default();
+        let approval_threshold = approval_threshold.unwrap_or(0);
+        require!(
+            (approval_threshold as usize) <= approvers.len(),
+            format!(
+                "approval_threshold ({}) must be equal to or less than the number of approvers ({})",
+                approval_threshold,
+                approvers.len()
+            )
+        );
+        require!(
+            approvers.is_empty() || approval_threshold > 0,
+            "approval_threshold must be greater than 0"
+        );
+        for (i, a) in approvers.iter().enumerate() {
+            require!(!approvers[..i].contains(a), format!("approver '{}' is duplicated", a));
+        }
+
+        //the fee is that of the funder at the moment
+        let current_fee_percent = self.get_fee_percent_of(funder_account_id.clone());
+
+        let now = env::block_timestamp();
+        if let Some(deadline) = release_deadline_at {
+            require!(
+                deadline > now,
+                format!("release_deadline_at ({}) must be in the future ({})", deadline, now)
+            );
+        }
+
+        let escrow_id = self.next_escrow_id();
+        let is_funded = actual_amount == agreed_amount;
+        let new_item = EscrowItem {
+            escrow_id: escrow_id.clone(),
+            agreed_amount,
+            current_amount: actual_amount,
+            status: if is_funded { Status::Active } else { Status::New },
+            funder_account_id,
+            beneficiary_account_id,
+            current_fee_percent,
+            milestones,
+            inserted_at: now,
+            funded_at: if is_funded { Some(now) } else { None },
+            release_deadline_at,
+            arbiter_account_id,
+            beneficiaries,
+            approvers,
+            approval_threshold,
+            approvals: Vec::new(),
+            settlement_proposal: None,
+            emergency_withdrawal: None,
+            storage_payer_id,
+            finished_at: None,
+            external_ref,
+            token_account_id,
+            storage_cost: 0,
+        };
+        if let Some(arbiter) = &new_item.arbiter_account_id {
+            require!(
+                *arbiter != new_item.funder_account_id && !Self::is_beneficiary(&new_item, arbiter),
+                "arbiter must be neither funder nor one of the beneficiaries"
+            );
+        }
+
+        self.save_item(&new_item);
+        escrow_id
+    }
+
+    /// records what the storage of a new escrow has cost its payer; returns the escrow
+    fn record_storage_cost(&mut self, escrow_id: &EscrowId, cost: Balance) -> EscrowItem {
+        let mut escrow_item = self.read_item(escrow_id).unwrap();
+        escrow_item.storage_cost = cost;
+        self.save_item(&escrow_item);
+        escrow_item
+    }
+
+    /// generates a unique escrow_id out of a counter;
+    /// the ids chosen by the callers before are skipped
+    fn next_escrow_id(&mut self) -> EscrowId {
+        loop {
+            self.last_escrow_id += 1;
+            let escrow_id = self.last_escrow_id.to_string();
+            if !self.items.contains_key(&escrow_id) {
+                return escrow_id;
+            }
+        }
+    }
+
+    /// tops up a New escrow with `amount` of NEAR or, if `token_account_id` is provided, of the tokens
+    fn deposit_item(
+        &mut self,
+        escrow_id: EscrowId,
+        caller: AccountId,
+        token_account_id: Option<AccountId>,
+        amount: Balance,
+    ) -> Balance {
+        self.pause_flags.require_not_paused(Some(Feature::NewDeals));
+        match self.read_item(&escrow_id) {
+            Some(mut escrow_item) => {
+                require!(escrow_item.status == Status::New, "this escrow isn't new");
+                let authoriz_cond = (self.owner_id == caller) || (escrow_item.funder_account_id == caller);
+                require!(
+                    authoriz_cond,
+                    "only funder or owner of this escrow may call this method"
+                );
+                require!(
+                    escrow_item.token_account_id == token_account_id,
+                    "this escrow is funded with a different currency"
+                );
+                require!(amount > 0, "deposit must be greater than 0");
+                let missing_amount = escrow_item.agreed_amount - escrow_item.current_amount;
+                require!(
+                    amount <= missing_amount,
+                    format!(
+                        "deposit ({}) must be equal to or less than the missing amount ({})",
+                        amount, missing_amount
+                    )
+                );
+
+                escrow_item.current_amount += amount;
+                EscrowEvent::EscrowFund {
+                    escrow_id: escrow_id.clone(),
+                    account_id: caller,
+                    amount: U128(amount),
+                    current_amount: U128(escrow_item.current_amount),
+                    token_account_id: escrow_item.token_account_id.clone(),
+                }
+                .emit();
+
+                if escrow_item.current_amount == escrow_item.agreed_amount {
+                    escrow_item.status = Status::Active;
+                    escrow_item.funded_at = Some(env::block_timestamp());
+                    log!("escrow_id '{}' is fully funded and active", escrow_id);
+                }
+
+                self.save_item(&escrow_item);
+                escrow_item.current_amount
+            }
+            None => env::panic_str(&format!("escrow_id '{}' not found", escrow_id)),
+        }
+    }
+
+    /// sends NEAR or, if `token_account_id` is provided, the tokens via `ft_transfer`
+    fn transfer(&self, token_account_id: &Option<AccountId>, receiver_id: AccountId, amount: Balance) -> Promise {
+        match token_account_id {
+            Some(token) => ext_ft::ext(token.clone())
+                .with_attached_deposit(1)
+                .with_static_gas(GAS_FOR_FT_TRANSFER)
+                .ft_transfer(receiver_id, U128(amount), None),
+            None => Promise::new(receiver_id).transfer(amount),
+        }
+    }
+
+    fn set_paused(&mut self, feature: Option<Feature>, paused: bool) -> bool {
+        self.require_role(Role::Pauser);
+        let changed = self.pause_flags.set(feature, paused);
+        if changed {
+            let account_id = env::predecessor_account_id();
+            match paused {
+                true => EscrowEvent::Pause { feature, account_id }.emit(),
+                false => EscrowEvent::Unpause { feature, account_id }.emit(),
+            }
+        }
+
+        changed
+    }
+
+    fn require_role(&self, role: Role) {
+        self.roles
+            .require_role(&self.owner_id, &env::predecessor_account_id(), role);
+    }
+
+    fn emit_escrow_create(escrow_item: &EscrowItem) {
+        EscrowEvent::EscrowCreate {
+            escrow_id: escrow_item.escrow_id.clone(),
+            funder_account_id: escrow_item.funder_account_id.clone(),
+            beneficiary_account_id: escrow_item.beneficiary_account_id.clone(),
+            agreed_amount: U128(escrow_item.agreed_amount),
+            deposit: U128(escrow_item.current_amount),
+            fee_percent: U128(escrow_item.current_fee_percent),
+            token_account_id: escrow_item.token_account_id.clone(),
+            external_ref: escrow_item.external_ref.clone(),
+        }
+        .emit();
+    }
+
+    /// reads an escrow, upgrading it to the latest layout
+    fn read_item(&self, escrow_id: &EscrowId) -> Option<EscrowItem> {
+        self.items.get(escrow_id).map(EscrowItem::from)
+    }
+
+    /// inserts or updates an escrow, in the latest layout, keeping the indexes up to date
+    fn save_item(&mut self, escrow_item: &EscrowItem) {
+        let escrow_id = &escrow_item.escrow_id;
+        let mut item = escrow_item.clone();
+        if item.status.is_final() && item.finished_at.is_none() {
+            item.finished_at = Some(env::block_timestamp());
+        }
+
+        match self.items.insert(escrow_id, &item.into()) {
+            Some(VersionedEscrowItem::V1(prev_item)) => {
+                if prev_item.status != escrow_item.status {
+                    index_remove(&mut self.items_by_status, &prev_item.status, escrow_id);
+                    index_add(&mut self.items_by_status, b"s", &escrow_item.status, escrow_id);
+                }
+            }
+            // a new escrow, or one of the layout deployed first, which isn't indexed yet
+            _ => {
+                index_add(
+                    &mut self.items_by_funder,
+                    b"f",
+                    &escrow_item.funder_account_id,
+                    escrow_id,
+                );
+                for account_id in Self::beneficiary_ids(escrow_item) {
+                    index_add(&mut self.items_by_beneficiary, b"b", &account_id, escrow_id);
+                }
+                index_add(&mut self.items_by_status, b"s", &escrow_item.status, escrow_id);
+                if let Some(external_ref) = &escrow_item.external_ref {
+                    index_add(&mut self.items_by_external_ref, b"x", external_ref, escrow_id);
+                }
+            }
+        }
+    }
+
+    /// removes an escrow along with its entries in the indexes
+    fn delete_item(&mut self, escrow_item: &EscrowItem) {
+        let escrow_id = &escrow_item.escrow_id;
+        // one of the layout deployed first isn't indexed until index_old_escrows gets to it
+        if let Some(VersionedEscrowItem::V0(_)) = self.items.remove(escrow_id) {
+            return;
+        }
+        index_remove(&mut self.items_by_funder, &escrow_item.funder_account_id, escrow_id);
+        for account_id in Self::beneficiary_ids(escrow_item) {
+            index_remove(&mut self.items_by_beneficiary, &account_id, escrow_id);
+        }
+        index_remove(&mut self.items_by_status, &escrow_item.status, escrow_id);
+        if let Some(external_ref) = &escrow_item.external_ref {
+            index_remove(&mut self.items_by_external_ref, external_ref, escrow_id);
+        }
+    }
+
+    /// whether an account is beneficiary_account_id or one of the ones with a share
+    fn is_beneficiary(escrow_item: &EscrowItem, account_id: &AccountId) -> bool {
+        escrow_item.beneficiary_account_id == *account_id
+            || escrow_item.beneficiaries.iter().any(|b| b.account_id == *account_id)
+    }
+
+    /// beneficiary_account_id and the ones with a share, if any
+    fn beneficiary_ids(escrow_item: &EscrowItem) -> Vec<AccountId> {
+        let mut account_ids = vec![escrow_item.beneficiary_account_id.clone()];
+        for b in escrow_item.beneficiaries.iter() {
+            if !account_ids.contains(&b.account_id) {
+                account_ids.push(b.account_id.clone());
+            }
+        }
+
+        account_ids
+    }
+
+    /// a page of the escrows of an index; the order of an index isn't stable:
+    /// removing an escrow moves the last one of the same key into its place,
+    /// so the pages read in between may skip or repeat an escrow
+    fn list_escrows<K: BorshSerialize>(
+        &self,
+        index: &EscrowIndex<K>,
+        key: &K,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<EscrowView> {
+        index::index_page(
+            index,
+            key,
+            from_index.unwrap_or(0),
+            limit.unwrap_or(Self::DEFAULT_PAGE_LIMIT),
+        )
+        .iter()
+        .filter_map(|escrow_id| self.read_item(escrow_id))
+        .map(EscrowView::from)
+        .collect()
+    }
+
+    fn require_approvals(escrow_item: &EscrowItem) {
+        require!(
+            escrow_item.approvals.len() as u32 >= escrow_item.approval_threshold,
+            format!(
+                "the release requires {} approvals; there are {}",
+                escrow_item.approval_threshold,
+                escrow_item.approvals.len()
+            )
+        );
+    }
+
+    /// credits the funds out of an escrow to the receiver, who then withdraws them
+    fn payout(&mut self, escrow_item: &EscrowItem, receiver_id: AccountId, amount: Balance) {
+        self.claims.credit(&receiver_id, &escrow_item.token_account_id, amount);
+    }
+
+    /// sends whatever is left in an escrow back to the funder, who then withdraws it with `withdraw_refund`
+    fn refund_to_funder(&mut self, escrow_item: &mut EscrowItem) {
+        //what's left after the milestones that have been released, if any
+        let amount_to_reimburse = escrow_item.current_amount;
+        require!(amount_to_reimburse > 0, "there's nothing to reimburse");
+
+        self.refunds.credit(
+            &escrow_item.funder_account_id,
+            &escrow_item.token_account_id,
+            amount_to_reimburse,
+        );
+        escrow_item.current_amount = 0;
+
+        EscrowEvent::EscrowReimburse {
+            escrow_id: escrow_item.escrow_id.clone(),
+            funder_account_id: escrow_item.funder_account_id.clone(),
+            amount: U128(amount_to_reimburse),
+            token_account_id: escrow_item.token_account_id.clone(),
+        }
+        .emit();
+    }
+
+    /// the absolute bounds are in NEAR, thus they don't apply to the escrows in tokens
+    fn fee_limits_of(&self, escrow_item: &EscrowItem) -> FeeLimits {
+        match escrow_item.token_account_id {
+            Some(_) => FeeLimits::default(),
+            None => self.fee_limits,
+        }
+    }
+
+    /// sends `amount` minus the fee to the beneficiary and the fee to the treasury
+    fn release_to_beneficiary(&mut self, escrow_item: &mut EscrowItem, amount: Balance) {
+        require!(
+            escrow_item.current_amount >= amount,
+            format!(
+                "current_amount ({}) must be equal to or greater than the amount to be released ({});",
+                escrow_item.current_amount, amount
+            )
+        );
+
+        let fees::FeeSplit {
+            net: amount_for_beneficiary,
+            fee: amount_for_owner,
+        } = fees::split_clamped(
+            amount,
+            escrow_item.current_fee_percent,
+            &self.fee_limits_of(escrow_item),
+            Self::FEE_ROUNDING,
+        );
+
+        //send funds to the beneficiaries, according to their shares;
+        //the dust left after rounding down goes to beneficiary_account_id
+        let mut amounts: Vec<(AccountId, Balance)> = escrow_item
+            .beneficiaries
+            .iter()
+            .map(|b| {
+                (
+                    b.account_id.clone(),
+                    fees::apply_bps(amount_for_beneficiary, b.share_bps, Rounding::Down),
+                )
+            })
+            .collect();
+        let dust = amount_for_beneficiary - amounts.iter().map(|(_, a)| a).sum::<Balance>();
+        match amounts
+            .iter_mut()
+            .find(|(account_id, _)| *account_id == escrow_item.beneficiary_account_id)
+        {
+            Some((_, a)) => *a += dust,
+            None => amounts.push((escrow_item.beneficiary_account_id.clone(), dust)),
+        }
+
+        let mut payouts = Vec::with_capacity(amounts.len());
+        for (account_id, amount) in amounts {
+            self.payout(escrow_item, account_id.clone(), amount);
+            escrow_item.current_amount -= amount;
+            payouts.push(Payout {
+                account_id,
+                amount: U128(amount),
+            });
+        }
+
+        //keep the fees in the treasury
+        self.treasury.accrue(&escrow_item.token_account_id, amount_for_owner);
+        escrow_item.current_amount -= amount_for_owner;
+
+        //the tiers are in NEAR
+        if escrow_item.token_account_id.is_none() {
+            self.fee_schedule.add_volume(&escrow_item.funder_account_id, amount);
+        }
+
+        EscrowEvent::EscrowRelease {
+            escrow_id: escrow_item.escrow_id.clone(),
+            amount: U128(amount),
+            fee: U128(amount_for_owner),
+            fee_percent: U128(escrow_item.current_fee_percent),
+            payouts,
+            token_account_id: escrow_item.token_account_id.clone(),
+        }
+        .emit();
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use migration::{EscrowItemV0, EscrowV0};
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, RuntimeFeesConfig, VMConfig};
+
+    /// covers the storage of an escrow on top of its agreed amount
+    const STORAGE_DEPOSIT: Balance = 10u128.pow(23);
+
+    fn get_context(is_view: bool) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        let account0: AccountId = "0.near".parse().unwrap();
+        builder
+            .current_account_id(account0)
+            .signer_account_id(accounts(0))
+            .predecessor_account_id(accounts(0))
+            .is_view(is_view);
+
+        builder
+    }
+
+    fn setup_contract() -> Escrow {
+        testing_env!(get_context(false).build());
+        Escrow::init(None, Some(1_000), None)
+    }
+
+    /// creates escrow "1" of `agreed_amount` for beneficiary accounts(1), with no options
+    fn create_escrow(contract: &mut Escrow, funder_account_id: AccountId, agreed_amount: Balance) {
+        contract.new(
+            None,
+            funder_account_id,
+            accounts(1),
+            U128(agreed_amount),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+    }
+
+    /// deposits STORAGE_DEPOSIT for the storage of `account_id`
+    fn register(contract: &mut Escrow, account_id: AccountId) {
+        testing_env!(get_context(false).attached_deposit(STORAGE_DEPOSIT).build());
+        contract.storage_deposit(Some(account_id), None);
+    }
+
+    /// has the owner accept "token.near", and `sender_id` let its storage deposit pay for the escrows it creates with it
+    fn accept_token(contract: &mut Escrow, sender_id: AccountId) -> AccountId {
+        let token: AccountId = "token.near".parse().unwrap();
+        testing_env!(get_context(false).build());
+        contract.add_accepted_token(token.clone());
+        testing_env!(get_context(false).predecessor_account_id(sender_id).build());
+        contract.authorize_ft_storage(true);
+        token
+    }
+
+    fn milestones() -> Vec<MilestoneArgs> {
+        vec![
+            MilestoneArgs {
+                name: "design".to_string(),
+                amount: U128(300),
+            },
+            MilestoneArgs {
+                name: "delivery".to_string(),
+                amount: U128(700),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_init_contract() {
+        let contract = setup_contract();
+        assert_eq!(contract.get_owner_id(), accounts(0));
+        assert_eq!(contract.get_base_fee_percent(), 1_000);
+    }
+
+    #[test]
+    fn test_roles() {
+        let mut contract = setup_contract();
+        assert!(contract.grant_role(accounts(1), Role::FeeManager));
+        assert!(!contract.grant_role(accounts(1), Role::FeeManager));
+        assert!(contract.has_role(accounts(1), Role::FeeManager));
+        assert!(!contract.has_role(accounts(1), Role::Operator));
+
+        testing_env!(get_context(false).predecessor_account_id(accounts(1)).build());
+        contract.set_base_fee_percent(500);
+        assert_eq!(contract.get_base_fee_percent(), 500);
+
+        testing_env!(get_context(false).build());
+        assert!(contract.revoke_role(accounts(1), Role::FeeManager));
+        assert!(!contract.has_role(accounts(1), Role::FeeManager));
+    }
+
+    #[test]
+    #[should_panic(expected = "only owner or fee_manager may call this method")]
+    fn test_set_fee_without_role() {
+        let mut contract = setup_contract();
+        testing_env!(get_context(false).predecessor_account_id(accounts(1)).build());
+        contract.set_base_fee_percent(500);
+    }
+
+    #[test]
+    fn test_refund_while_paused() {
+        let mut contract = setup_contract();
+        testing_env!(get_context(false).attached_deposit(1000 + STORAGE_DEPOSIT).build());
+        create_escrow(&mut contract, accounts(2), 1000);
+
+        contract.grant_role(accounts(3), Role::Pauser);
+        testing_env!(get_context(false).predecessor_account_id(accounts(3)).build());
+        assert!(contract.pause(None));
+        assert!(contract.is_paused(Some(Feature::Payouts)));
+
+        testing_env!(get_context(false).predecessor_account_id(accounts(1)).build());
+        contract.reimburse_funder("1".to_string());
+        assert_eq!(contract.get_refundable_balance(accounts(2), None).0, 1000);
+        assert_eq!(contract.get_claimable_balance(accounts(2), None).0, 0);
+
+        //the refunded funds may be withdrawn while paused
+        testing_env!(get_context(false)
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(1)
+            .build());
+        contract.withdraw_refund(Some(U128(400)), None);
+        assert_eq!(contract.get_refundable_balance(accounts(2), None).0, 600);
+
+        testing_env!(
+            get_context(false)
+                .predecessor_account_id("0.near".parse().unwrap())
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed],
+        );
+        assert!(!contract.on_withdraw_refund(accounts(2), None, U128(400)));
+        assert_eq!(contract.get_refundable_balance(accounts(2), None).0, 1000);
+    }
+
+    #[test]
+    #[should_panic(expected = "payouts are paused")]
+    fn test_release_while_payouts_paused() {
+        let mut contract = setup_contract();
+        testing_env!(get_context(false).attached_deposit(1000 + STORAGE_DEPOSIT).build());
+        create_escrow(&mut contract, accounts(2), 1000);
+        contract.pause(Some(Feature::Payouts));
+        contract.release_deposit("1".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "payouts are paused")]
+    fn test_withdraw_while_payouts_paused() {
+        let mut contract = setup_contract();
+        testing_env!(get_context(false).attached_deposit(1000 + STORAGE_DEPOSIT).build());
+        create_escrow(&mut contract, accounts(2), 1000);
+        contract.release_deposit("1".to_string());
+        contract.pause(Some(Feature::Payouts));
+
+        testing_env!(get_context(false)
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .build());
+        contract.withdraw(None, None);
+    }
+
+    #[test]
+    fn test_emergency_withdrawal() {
+        let mut contract = setup_contract();
+        testing_env!(get_context(false).attached_deposit(1000 + STORAGE_DEPOSIT).build());
+        create_escrow(&mut contract, accounts(2), 1000);
+        contract.announce_emergency_withdrawal("1".to_string(), "a bug".to_string(), accounts(2));
+        let executable_at = contract
+            .get_item("1".to_string())
+            .unwrap()
+            .emergency_withdrawal
+            .unwrap()
+            .executable_at;
+        assert_eq!(executable_at, contract.get_emergency_withdrawal_delay());
+
+        testing_env!(get_context(false).block_timestamp(executable_at).build());
+        contract.execute_emergency_withdrawal("1".to_string());
+        assert_eq!(contract.get_refundable_balance(accounts(2), None).0, 1000);
+        assert_eq!(contract.get_claimable_balance(accounts(0), None).0, 0);
+        assert_eq!(contract.get_accrued_fees(None).0, 0);
+        assert_eq!(contract.read_item(&"1".to_string()).unwrap().status, Status::Reimbursed);
+    }
+
+    #[test]
+    fn test_emergency_withdrawal_to_beneficiaries() {
+        let mut contract = setup_contract();
+        testing_env!(get_context(false).attached_deposit(1000 + STORAGE_DEPOSIT).build());
+        let beneficiaries = vec![
+            BeneficiaryShare {
+                account_id: accounts(1),
+                share_bps: 5_000,
+            },
+            BeneficiaryShare {
+                account_id: accounts(3),
+                share_bps: 5_000,
+            },
+        ];
+        contract.new(
+            None,
+            accounts(0),
+            accounts(1),
+            U128(1000),
+            None,
+            None,
+            None,
+            Some(beneficiaries),
+            None,
+            None,
+        );
+        contract.announce_emergency_withdrawal("1".to_string(), "a bug".to_string(), accounts(3));
+
+        //released by the shares, the fee taken off
+        let delay = contract.get_emergency_withdrawal_delay();
+        testing_env!(get_context(false).block_timestamp(delay).build());
+        contract.execute_emergency_withdrawal("1".to_string());
+        assert_eq!(contract.get_claimable_balance(accounts(1), None).0, 450);
+        assert_eq!(contract.get_claimable_balance(accounts(3), None).0, 450);
+        assert_eq!(contract.get_accrued_fees(None).0, 100);
+        let item = contract.read_item(&"1".to_string()).unwrap();
+        assert_eq!(item.status, Status::PayedOff);
+        assert_eq!(item.current_amount, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "this escrow doesn't hold funds anymore or is disputed")]
+    fn test_emergency_withdrawal_of_disputed_escrow() {
+        let mut contract = setup_contract();
+        testing_env!(get_context(false).attached_deposit(1000 + STORAGE_DEPOSIT).build());
+        contract.new(
+            None,
+            accounts(0),
+            accounts(1),
+            U128(1000),
+            None,
+            None,
+            Some(accounts(2)),
+            None,
+            None,
+            None,
+        );
+        contract.announce_emergency_withdrawal("1".to_string(), "a bug".to_string(), accounts(1));
+
+        //up to the arbiter from now on
+        testing_env!(get_context(false).predecessor_account_id(accounts(1)).build());
+        contract.raise_dispute("1".to_string());
+
+        let delay = contract.get_emergency_withdrawal_delay();
+        testing_env!(get_context(false).block_timestamp(delay).build());
+        contract.execute_emergency_withdrawal("1".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "the emergency withdrawal may be executed at")]
+    fn test_emergency_withdrawal_before_delay() {
+        let mut contract = setup_contract();
+        testing_env!(get_context(false).attached_deposit(1000 + STORAGE_DEPOSIT).build());
+        create_escrow(&mut contract, accounts(2), 1000);
+        contract.announce_emergency_withdrawal("1".to_string(), "a bug".to_string(), accounts(1));
+        contract.execute_emergency_withdrawal("1".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "the delay must be at least")]
+    fn test_emergency_withdrawal_delay_too_short() {
+        let mut contract = setup_contract();
+        contract.set_emergency_withdrawal_delay(0);
+    }
+
+    #[test]
+    fn test_emergency_withdrawal_delay_applies_to_later_announcements() {
+        let mut contract = setup_contract();
+        testing_env!(get_context(false).attached_deposit(1000 + STORAGE_DEPOSIT).build());
+        create_escrow(&mut contract, accounts(2), 1000);
+        create_escrow(&mut contract, accounts(2), 1000);
+        contract.announce_emergency_withdrawal("1".to_string(), "a bug".to_string(), accounts(2));
+
+        let delay = Escrow::MIN_EMERGENCY_WITHDRAWAL_DELAY;
+        contract.set_emergency_withdrawal_delay(delay);
+        assert_eq!(
+            near_sdk::test_utils::get_logs().last().unwrap(),
+            &format!(
+                r#"EVENT_JSON:{{"standard":"antioquia_escrow","version":"1.0.0","event":"emergency_withdrawal_delay_change","data":{{"old_delay":{},"new_delay":{}}}}}"#,
+                Escrow::DEFAULT_EMERGENCY_WITHDRAWAL_DELAY,
+                delay
+            )
+        );
+
+        contract.announce_emergency_withdrawal("2".to_string(), "a bug".to_string(), accounts(2));
+        let executable_at = |contract: &Escrow, escrow_id: &str| {
+            contract
+                .get_item(escrow_id.to_string())
+                .unwrap()
+                .emergency_withdrawal
+                .unwrap()
+                .executable_at
+        };
+        assert_eq!(
+            executable_at(&contract, "1"),
+            Escrow::DEFAULT_EMERGENCY_WITHDRAWAL_DELAY
+        );
+        assert_eq!(executable_at(&contract, "2"), delay);
+    }
+
+    #[test]
+    #[should_panic(expected = "the funds may be withdrawn only to funder or a beneficiary")]
+    fn test_emergency_withdrawal_to_owner() {
+        let mut contract = setup_contract();
+        testing_env!(get_context(false).attached_deposit(1000 + STORAGE_DEPOSIT).build());
+        create_escrow(&mut contract, accounts(2), 1000);
+        contract.announce_emergency_withdrawal("1".to_string(), "a bug".to_string(), accounts(0));
+    }
+
+    #[test]
+    fn test_transfer_ownership() {
+        let mut contract = setup_contract();
+        contract.propose_owner(accounts(1));
+        assert_eq!(contract.get_proposed_owner_id(), Some(accounts(1)));
+        contract.cancel_ownership_transfer();
+        assert_eq!(contract.get_proposed_owner_id(), None);
+
+        contract.propose_owner(accounts(2));
+        testing_env!(get_context(false).predecessor_account_id(accounts(2)).build());
+        contract.accept_ownership();
+        assert_eq!(contract.get_owner_id(), accounts(2));
+        assert_eq!(contract.get_proposed_owner_id(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "only the proposed owner may call this method")]
+    fn test_accept_ownership_by_other_account() {
+        let mut contract = setup_contract();
+        contract.propose_owner(accounts(1));
+        testing_env!(get_context(false).predecessor_account_id(accounts(2)).build());
+        contract.accept_ownership();
+    }
+
+    #[test]
+    fn test_release_milestones() {
+        let mut contract = setup_contract();
+        testing_env!(get_context(false).attached_deposit(1000 + STORAGE_DEPOSIT).build());
+        contract.new(
+            None,
+            accounts(0),
+            accounts(1),
+            U128(1000),
+            Some(milestones()),
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+
+        contract.release_milestone("1".to_string(), "design".to_string());
+        let item = contract.read_item(&"1".to_string()).unwrap();
+        assert_eq!(item.status, Status::Active);
+        assert_eq!(item.current_amount, 700);
+
+        contract.release_milestone("1".to_string(), "delivery".to_string());
+        let item = contract.read_item(&"1".to_string()).unwrap();
+        assert_eq!(item.status, Status::PayedOff);
+        assert_eq!(item.current_amount, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "has already been released")]
+    fn test_release_milestone_twice() {
+        let mut contract = setup_contract();
+        testing_env!(get_context(false).attached_deposit(1000 + STORAGE_DEPOSIT).build());
+        contract.new(
+            None,
+            accounts(0),
+            accounts(1),
+            U128(1000),
+            Some(milestones()),
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+
+        contract.release_milestone("1".to_string(), "design".to_string());
+        contract.release_milestone("1".to_string(), "design".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "must be equal to agreed_amount")]
+    fn test_milestones_must_add_up() {
+        let mut contract = setup_contract();
+        testing_env!(get_context(false).attached_deposit(900).build());
+        contract.new(
+            None,
+            accounts(0),
+            accounts(1),
+            U128(900),
+            Some(milestones()),
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+    }
+
+    #[test]
+    fn test_reclaim_expired() {
+        let mut contract = setup_contract();
+        testing_env!(get_context(false)
+            .attached_deposit(1000 + STORAGE_DEPOSIT)
+            .block_timestamp(100)
+            .build());
+        contract.new(
+            None,
+            accounts(0),
+            accounts(1),
+            U128(1000),
+            None,
+            Some(200),
+            None,
+            None,
+            None,
+            None,
+        );
+
+        testing_env!(get_context(false).block_timestamp(201).build());
+        contract.reclaim_expired("1".to_string());
+        let item = contract.read_item(&"1".to_string()).unwrap();
+        assert_eq!(item.status, Status::Reimbursed);
+        assert_eq!(item.current_amount, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "hasn't passed yet")]
+    fn test_reclaim_before_deadline() {
+        let mut contract = setup_contract();
+        testing_env!(get_context(false)
+            .attached_deposit(1000 + STORAGE_DEPOSIT)
+            .block_timestamp(100)
+            .build());
+        contract.new(
+            None,
+            accounts(0),
+            accounts(1),
+            U128(1000),
+            None,
+            Some(200),
+            None,
+            None,
+            None,
+            None,
+        );
+
+        testing_env!(get_context(false).block_timestamp(150).build());
+        contract.reclaim_expired("1".to_string());
+    }
+
+    #[test]
+    fn test_resolve_dispute() {
+        let mut contract = setup_contract();
+        testing_env!(get_context(false).attached_deposit(1000 + STORAGE_DEPOSIT).build());
+        contract.new(
+            None,
+            accounts(0),
+            accounts(1),
+            U128(1000),
+            None,
+            None,
+            Some(accounts(2)),
+            None,
+            None,
+            None,
+        );
+
+        testing_env!(get_context(false).predecessor_account_id(accounts(1)).build());
+        contract.raise_dispute("1".to_string());
+        assert_eq!(contract.read_item(&"1".to_string()).unwrap().status, Status::Disputed);
+
+        testing_env!(get_context(false).predecessor_account_id(accounts(2)).build());
+        contract.resolve_dispute("1".to_string(), U128(700));
+        let item = contract.read_item(&"1".to_string()).unwrap();
+        assert_eq!(item.status, Status::Resolved);
+        assert_eq!(item.current_amount, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "this escrow isn't active")]
+    fn test_disputed_escrow_is_frozen() {
+        let mut contract = setup_contract();
+        testing_env!(get_context(false).attached_deposit(1000 + STORAGE_DEPOSIT).build());
+        contract.new(
+            None,
+            accounts(0),
+            accounts(1),
+            U128(1000),
+            None,
+            None,
+            Some(accounts(2)),
+            None,
+            None,
+            None,
+        );
+        contract.raise_dispute("1".to_string());
+        contract.release_deposit("1".to_string());
+    }
+
+    #[test]
+    fn test_settle_with_consent_of_both_parties() {
+        let mut contract = setup_contract();
+        testing_env!(get_context(false).attached_deposit(1000 + STORAGE_DEPOSIT).build());
+        create_escrow(&mut contract, accounts(2), 1000);
+
+        testing_env!(get_context(false).predecessor_account_id(accounts(2)).build());
+        contract.settle("1".to_string(), U128(700));
+        assert_eq!(contract.read_item(&"1".to_string()).unwrap().status, Status::Active);
+
+        testing_env!(get_context(false).predecessor_account_id(accounts(1)).build());
+        contract.settle("1".to_string(), U128(700));
+        let item = contract.read_item(&"1".to_string()).unwrap();
+        assert_eq!(item.status, Status::Settled);
+        assert_eq!(item.current_amount, 0);
+    }
+
+    #[test]
+    fn test_installment_funding() {
+        let mut contract = setup_contract();
+        register(&mut contract, accounts(0));
+        let agreed_amount = 10 * STORAGE_DEPOSIT;
+        testing_env!(get_context(false).attached_deposit(STORAGE_DEPOSIT).build());
+        create_escrow(&mut contract, accounts(0), agreed_amount);
+        let item = contract.read_item(&"1".to_string()).unwrap();
+        assert_eq!(item.status, Status::New);
+
+        //the storage has been paid for out of the storage deposit rather than the first installment
+        assert_eq!(item.current_amount, STORAGE_DEPOSIT);
+        testing_env!(get_context(false)
+            .attached_deposit(agreed_amount - item.current_amount)
+            .build());
+        assert_eq!(contract.deposit("1".to_string()), U128(agreed_amount));
+        let item = contract.read_item(&"1".to_string()).unwrap();
+        assert_eq!(item.status, Status::Active);
+        assert!(item.funded_at.is_some());
+    }
+
+    #[test]
+    fn test_cancel_new_escrow() {
+        let mut contract = setup_contract();
+        register(&mut contract, accounts(0));
+        testing_env!(get_context(false).attached_deposit(STORAGE_DEPOSIT).build());
+        create_escrow(&mut contract, accounts(0), 10 * STORAGE_DEPOSIT);
+
+        contract.cancel("1".to_string());
+        let item = contract.read_item(&"1".to_string()).unwrap();
+        assert_eq!(item.status, Status::Canceled);
+        assert_eq!(item.current_amount, 0);
+    }
+
+    #[test]
+    fn test_storage_is_charged_and_refunded() {
+        let mut contract = setup_contract();
+        testing_env!(get_context(false).attached_deposit(1000 + STORAGE_DEPOSIT).build());
+        create_escrow(&mut contract, accounts(0), 1000);
+        let item = contract.read_item(&"1".to_string()).unwrap();
+        assert_eq!(item.current_amount, 1000);
+        assert_eq!(item.storage_payer_id, Some(accounts(0)));
+        assert!(item.storage_cost > 0);
+
+        contract.release_deposit("1".to_string());
+        contract.remove_item("1".to_string());
+        assert!(contract.read_item(&"1".to_string()).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "must cover the amount (1000) plus the storage cost")]
+    fn test_storage_not_covered() {
+        let mut contract = setup_contract();
+        testing_env!(get_context(false).attached_deposit(1000).build());
+        create_escrow(&mut contract, accounts(0), 1000);
+    }
+
+    #[test]
+    #[should_panic(expected = "must be registered with storage_deposit to fund an escrow partially")]
+    fn test_partial_funding_unregistered() {
+        let mut contract = setup_contract();
+        testing_env!(get_context(false).attached_deposit(STORAGE_DEPOSIT).build());
+        create_escrow(&mut contract, accounts(0), 10 * STORAGE_DEPOSIT);
+    }
+
+    #[test]
+    fn test_storage_deposit_pays_for_escrows() {
+        let mut contract = setup_contract();
+        register(&mut contract, accounts(0));
+        let balance = contract.storage_balance_of(accounts(0)).unwrap();
+        assert_eq!(balance.total.0, STORAGE_DEPOSIT);
+        let available = balance.available.0;
+
+        //nothing but agreed_amount is attached
+        testing_env!(get_context(false).attached_deposit(1000).build());
+        create_escrow(&mut contract, accounts(0), 1000);
+        assert_eq!(contract.read_item(&"1".to_string()).unwrap().current_amount, 1000);
+        assert!(contract.storage_balance_of(accounts(0)).unwrap().available.0 < available);
+
+        let storage_cost = contract.read_item(&"1".to_string()).unwrap().storage_cost;
+        assert_eq!(
+            contract.storage_balance_of(accounts(0)).unwrap().available.0,
+            available - storage_cost
+        );
+
+        contract.release_deposit("1".to_string());
+        contract.remove_item("1".to_string());
+        //finished_at has taken a few bytes more since, at the contract's expense; they don't get refunded
+        let balance = contract.storage_balance_of(accounts(0)).unwrap();
+        assert_eq!(balance.available.0, available);
+        assert_eq!(balance.total.0, STORAGE_DEPOSIT);
+
+        testing_env!(get_context(false).attached_deposit(1).build());
+        assert!(contract.storage_unregister(None));
+        assert_eq!(contract.storage_balance_of(accounts(0)), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "still take storage")]
+    fn test_storage_unregister_with_escrows() {
+        let mut contract = setup_contract();
+        register(&mut contract, accounts(0));
+        testing_env!(get_context(false).attached_deposit(1000).build());
+        create_escrow(&mut contract, accounts(0), 1000);
+
+        testing_env!(get_context(false).attached_deposit(1).build());
+        contract.storage_unregister(None);
+    }
+
+    #[test]
+    #[should_panic(expected = "must be registered with storage_deposit")]
+    fn test_ft_on_transfer_requires_registration() {
+        let mut contract = setup_contract();
+        let token = accept_token(&mut contract, accounts(2));
+        testing_env!(get_context(false).predecessor_account_id(token).build());
+        let msg = format!(
+            r#"{{"new": {{"external_ref": "e1", "funder_account_id": "{}", "beneficiary_account_id": "{}", "agreed_amount": "1000"}}}}"#,
+            accounts(2),
+            accounts(1)
+        );
+        contract.ft_on_transfer(accounts(2), U128(1000), msg);
+    }
+
+    #[test]
+    #[should_panic(expected = "must call authorize_ft_storage")]
+    fn test_ft_on_transfer_requires_storage_authorization() {
+        let mut contract = setup_contract();
+        register(&mut contract, accounts(2));
+        let token = accept_token(&mut contract, accounts(2));
+        testing_env!(get_context(false).predecessor_account_id(accounts(2)).build());
+        contract.authorize_ft_storage(false);
+        assert!(!contract.is_ft_storage_authorized(accounts(2)));
+
+        testing_env!(get_context(false).predecessor_account_id(token).build());
+        let msg = format!(
+            r#"{{"new": {{"external_ref": "e1", "funder_account_id": "{}", "beneficiary_account_id": "{}", "agreed_amount": "1000"}}}}"#,
+            accounts(2),
+            accounts(1)
+        );
+        contract.ft_on_transfer(accounts(2), U128(1000), msg);
+    }
+
+    #[test]
+    #[should_panic(expected = "the token fake.near isn't accepted")]
+    fn test_ft_on_transfer_of_token_not_accepted() {
+        let mut contract = setup_contract();
+        register(&mut contract, accounts(2));
+        accept_token(&mut contract, accounts(2));
+
+        //any contract may pose as a token, passing any sender_id
+        testing_env!(get_context(false)
+            .predecessor_account_id("fake.near".parse().unwrap())
+            .build());
+        let msg = format!(
+            r#"{{"new": {{"external_ref": "e1", "funder_account_id": "{}", "beneficiary_account_id": "{}", "agreed_amount": "1000"}}}}"#,
+            accounts(2),
+            accounts(1)
+        );
+        contract.ft_on_transfer(accounts(2), U128(1000), msg);
+    }
+
+    #[test]
+    fn test_accepted_tokens() {
+        let mut contract = setup_contract();
+        let token: AccountId = "token.near".parse().unwrap();
+        assert!(contract.add_accepted_token(token.clone()));
+        assert!(!contract.add_accepted_token(token.clone()));
+        assert_eq!(contract.get_accepted_tokens(), vec![token.clone()]);
+
+        assert!(contract.remove_accepted_token(token.clone()));
+        assert!(!contract.remove_accepted_token(token));
+        assert!(contract.get_accepted_tokens().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "only owner may call this method")]
+    fn test_add_accepted_token_by_other_account() {
+        let mut contract = setup_contract();
+        testing_env!(get_context(false).predecessor_account_id(accounts(1)).build());
+        contract.add_accepted_token("token.near".parse().unwrap());
+    }
+
+    #[test]
+    fn test_ft_on_transfer_creates_and_funds_escrow() {
+        let mut contract = setup_contract();
+        register(&mut contract, accounts(2));
+        let token = accept_token(&mut contract, accounts(2));
+        testing_env!(get_context(false).predecessor_account_id(token.clone()).build());
+
+        let msg = format!(
+            r#"{{"new": {{"external_ref": "e1", "funder_account_id": "{}", "beneficiary_account_id": "{}", "agreed_amount": "1000"}}}}"#,
+            accounts(2),
+            accounts(1)
+        );
+        contract.ft_on_transfer(accounts(2), U128(400), msg);
+        let item = contract.read_item(&"1".to_string()).unwrap();
+        assert_eq!(item.token_account_id, Some(token));
+        assert_eq!(item.status, Status::New);
+        assert_eq!(item.external_ref, Some("e1".to_string()));
+
+        contract.ft_on_transfer(accounts(2), U128(600), r#"{"deposit": {"escrow_id": "1"}}"#.to_string());
+        let item = contract.read_item(&"1".to_string()).unwrap();
+        assert_eq!(item.status, Status::Active);
+        assert_eq!(item.current_amount, 1000);
+    }
+
+    #[test]
+    #[should_panic(expected = "funded with a different currency")]
+    fn test_deposit_native_into_ft_escrow() {
+        let mut contract = setup_contract();
+        register(&mut contract, accounts(0));
+        let token = accept_token(&mut contract, accounts(0));
+        testing_env!(get_context(false).predecessor_account_id(token).build());
+        let msg = format!(
+            r#"{{"new": {{"external_ref": "e1", "funder_account_id": "{}", "beneficiary_account_id": "{}", "agreed_amount": "1000"}}}}"#,
+            accounts(0),
+            accounts(1)
+        );
+        contract.ft_on_transfer(accounts(0), U128(400), msg);
+
+        testing_env!(get_context(false).attached_deposit(600).build());
+        contract.deposit("1".to_string());
+    }
+
+    #[test]
+    fn test_withdraw_credited_funds() {
+        let mut contract = setup_contract();
+        testing_env!(get_context(false).attached_deposit(1000 + STORAGE_DEPOSIT).build());
+        create_escrow(&mut contract, accounts(0), 1000);
+        contract.release_deposit("1".to_string());
+        assert_eq!(contract.get_claimable_balance(accounts(1), None).0, 900);
+        assert_eq!(contract.get_accrued_fees(None).0, 100);
+
+        testing_env!(get_context(false)
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .build());
+        contract.withdraw(Some(U128(400)), None);
+        assert_eq!(contract.get_claimable_balance(accounts(1), None).0, 500);
+
+        testing_env!(
+            get_context(false)
+                .predecessor_account_id("0.near".parse().unwrap())
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed],
+        );
+        assert!(!contract.on_withdraw(accounts(1), None, U128(400)));
+        assert_eq!(contract.get_claimable_balance(accounts(1), None).0, 900);
+    }
+
+    #[test]
+    fn test_release_emits_event() {
+        let mut contract = setup_contract();
+        testing_env!(get_context(false).attached_deposit(1000 + STORAGE_DEPOSIT).build());
+        create_escrow(&mut contract, accounts(0), 1000);
+        contract.release_deposit("1".to_string());
+
+        let logs = near_sdk::test_utils::get_logs();
+        assert_eq!(
+            logs.last().unwrap(),
+            &format!(
+                "EVENT_JSON:{}",
+                r#"{"standard":"antioquia_escrow","version":"1.0.0","event":"escrow_release","data":{"escrow_id":"1","amount":"1000","fee":"100","fee_percent":"1000","payouts":[{"account_id":"bob","amount":"900"}],"token_account_id":null}}"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_release_clamps_fee() {
+        let mut contract = setup_contract();
+        contract.set_fee_limits(U128(150), Some(U128(200)));
+        assert_eq!(contract.get_min_fee().0, 150);
+        assert_eq!(contract.get_max_fee(), Some(U128(200)));
+
+        testing_env!(get_context(false).attached_deposit(1000 + STORAGE_DEPOSIT).build());
+        create_escrow(&mut contract, accounts(2), 1000);
+        contract.release_deposit("1".to_string());
+
+        //10% of 1000 is 100, below min_fee
+        assert_eq!(contract.get_claimable_balance(accounts(1), None).0, 850);
+        assert_eq!(contract.get_accrued_fees(None).0, 150);
+    }
+
+    #[test]
+    fn test_fee_tiers_and_overrides() {
+        let mut contract = setup_contract();
+        contract.set_fee_tiers(vec![FeeTier {
+            min_volume: U128(1000),
+            fee_bps: 500,
+        }]);
+        contract.set_fee_override(accounts(3), Some(0));
+        assert_eq!(contract.get_fee_percent_of(accounts(2)), 1_000);
+        assert_eq!(contract.get_fee_percent_of(accounts(3)), 0);
+
+        testing_env!(get_context(false).attached_deposit(1000 + STORAGE_DEPOSIT).build());
+        create_escrow(&mut contract, accounts(2), 1000);
+        contract.release_deposit("1".to_string());
+        assert_eq!(contract.get_volume(accounts(2)).0, 1000);
+        assert_eq!(contract.get_fee_percent_of(accounts(2)), 500);
+
+        //the fee of an existing escrow doesn't change
+        contract.set_base_fee_percent(2_000);
+        assert_eq!(contract.read_item(&"1".to_string()).unwrap().current_fee_percent, 1_000);
+    }
+
+    #[test]
+    fn test_withdraw_fees_to_treasury() {
+        let mut contract = setup_contract();
+        contract.set_treasury_account_id(accounts(3));
+        testing_env!(get_context(false).attached_deposit(1000 + STORAGE_DEPOSIT).build());
+        create_escrow(&mut contract, accounts(2), 1000);
+        contract.release_deposit("1".to_string());
+
+        testing_env!(get_context(false)
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        contract.withdraw_fees(Some(U128(60)), None);
+        assert_eq!(contract.get_accrued_fees(None).0, 40);
+
+        testing_env!(
+            get_context(false)
+                .predecessor_account_id("0.near".parse().unwrap())
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed],
+        );
+        assert!(!contract.on_withdraw_fees(None, U128(60)));
+        assert_eq!(contract.get_accrued_fees(None).0, 100);
+        assert_eq!(contract.get_total_accrued_fees(None).0, 100);
+    }
+
+    #[test]
+    fn test_release_to_several_beneficiaries() {
+        let mut contract = setup_contract();
+        contract.set_fee_override(accounts(0), Some(0));
+        testing_env!(get_context(false).attached_deposit(1000 + STORAGE_DEPOSIT).build());
+        let beneficiaries = vec![
+            BeneficiaryShare {
+                account_id: accounts(1),
+                share_bps: 3_333,
+            },
+            BeneficiaryShare {
+                account_id: accounts(2),
+                share_bps: 6_667,
+            },
+        ];
+        contract.new(
+            None,
+            accounts(0),
+            accounts(1),
+            U128(1000),
+            None,
+            None,
+            None,
+            Some(beneficiaries),
+            None,
+            None,
+        );
+        contract.release_deposit("1".to_string());
+
+        //1000 * 0.3333 = 333.3 and 1000 * 0.6667 = 666.7, the dust goes to accounts(1)
+        assert_eq!(contract.get_claimable_balance(accounts(2), None).0, 666);
+        assert_eq!(contract.get_claimable_balance(accounts(1), None).0, 334);
+        assert_eq!(contract.read_item(&"1".to_string()).unwrap().current_amount, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "arbiter must be neither funder nor one of the beneficiaries")]
+    fn test_arbiter_with_share() {
+        let mut contract = setup_contract();
+        testing_env!(get_context(false).attached_deposit(1000 + STORAGE_DEPOSIT).build());
+        let beneficiaries = vec![
+            BeneficiaryShare {
+                account_id: accounts(1),
+                share_bps: 5_000,
+            },
+            BeneficiaryShare {
+                account_id: accounts(2),
+                share_bps: 5_000,
+            },
+        ];
+        contract.new(
+            None,
+            accounts(0),
+            accounts(1),
+            U128(1000),
+            None,
+            None,
+            Some(accounts(2)),
+            Some(beneficiaries),
+            None,
+            None,
+        );
+    }
+
+    /// creates escrow "1" of 1000 for accounts(1) with arbiter accounts(2) and approvers accounts(3) and accounts(4),
+    /// both of which have to approve a release
+    fn create_approved_escrow(contract: &mut Escrow) {
+        testing_env!(get_context(false).attached_deposit(1000 + STORAGE_DEPOSIT).build());
+        contract.new(
+            None,
+            accounts(0),
+            accounts(1),
+            U128(1000),
+            None,
+            None,
+            Some(accounts(2)),
+            None,
+            Some(vec![accounts(3), accounts(4)]),
+            Some(2),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "the release requires 2 approvals; there are 0")]
+    fn test_owner_settlement_requires_approvals() {
+        let mut contract = setup_contract();
+        create_approved_escrow(&mut contract);
+        contract.settle("1".to_string(), U128(700));
+    }
+
+    #[test]
+    fn test_owner_settlement_refunds_without_approvals() {
+        let mut contract = setup_contract();
+        create_approved_escrow(&mut contract);
+        contract.settle("1".to_string(), U128(0));
+        assert_eq!(contract.read_item(&"1".to_string()).unwrap().status, Status::Settled);
+        assert_eq!(contract.get_refundable_balance(accounts(0), None).0, 1000);
+    }
+
+    #[test]
+    #[should_panic(expected = "the release requires 2 approvals; there are 1")]
+    fn test_resolve_dispute_requires_approvals() {
+        let mut contract = setup_contract();
+        create_approved_escrow(&mut contract);
+        testing_env!(get_context(false).predecessor_account_id(accounts(3)).build());
+        contract.approve_release("1".to_string());
+        testing_env!(get_context(false).predecessor_account_id(accounts(1)).build());
+        contract.raise_dispute("1".to_string());
+
+        testing_env!(get_context(false).predecessor_account_id(accounts(2)).build());
+        contract.resolve_dispute("1".to_string(), U128(700));
+    }
+
+    #[test]
+    fn test_resolve_approved_dispute() {
+        let mut contract = setup_contract();
+        create_approved_escrow(&mut contract);
+        testing_env!(get_context(false).predecessor_account_id(accounts(1)).build());
+        contract.raise_dispute("1".to_string());
+        for approver in [accounts(3), accounts(4)] {
+            testing_env!(get_context(false).predecessor_account_id(approver).build());
+            contract.approve_release("1".to_string());
+        }
+
+        testing_env!(get_context(false).predecessor_account_id(accounts(2)).build());
+        contract.resolve_dispute("1".to_string(), U128(700));
+        assert_eq!(contract.read_item(&"1".to_string()).unwrap().status, Status::Resolved);
+    }
+
+    /// creates escrow "1" of 1000 funded by accounts(4) for accounts(1) and accounts(2), half and half,
+    /// with arbiter accounts(3)
+    fn create_shared_escrow(contract: &mut Escrow) {
+        testing_env!(get_context(false).attached_deposit(1000 + STORAGE_DEPOSIT).build());
+        let beneficiaries = vec![
+            BeneficiaryShare {
+                account_id: accounts(1),
+                share_bps: 5_000,
+            },
+            BeneficiaryShare {
+                account_id: accounts(2),
+                share_bps: 5_000,
+            },
+        ];
+        contract.new(
+            None,
+            accounts(4),
+            accounts(1),
+            U128(1000),
+            None,
+            None,
+            Some(accounts(3)),
+            Some(beneficiaries),
+            None,
+            None,
+        );
+    }
+
+    #[test]
+    fn test_co_beneficiary_raises_dispute() {
+        let mut contract = setup_contract();
+        create_shared_escrow(&mut contract);
+
+        testing_env!(get_context(false).predecessor_account_id(accounts(2)).build());
+        contract.raise_dispute("1".to_string());
+        assert_eq!(contract.read_item(&"1".to_string()).unwrap().status, Status::Disputed);
+    }
+
+    #[test]
+    fn test_co_beneficiary_settles() {
+        let mut contract = setup_contract();
+        create_shared_escrow(&mut contract);
+
+        //a beneficiary may not accept the proposal of another one
+        testing_env!(get_context(false).predecessor_account_id(accounts(1)).build());
+        contract.settle("1".to_string(), U128(600));
+        testing_env!(get_context(false).predecessor_account_id(accounts(2)).build());
+        contract.settle("1".to_string(), U128(600));
+        let item = contract.read_item(&"1".to_string()).unwrap();
+        assert_eq!(item.status, Status::Active);
+        assert_eq!(item.settlement_proposal.unwrap().proposed_by, accounts(2));
+
+        testing_env!(get_context(false).predecessor_account_id(accounts(4)).build());
+        contract.settle("1".to_string(), U128(600));
+        assert_eq!(contract.read_item(&"1".to_string()).unwrap().status, Status::Settled);
+    }
+
+    #[test]
+    fn test_release_requires_approvals() {
+        let mut contract = setup_contract();
+        testing_env!(get_context(false).attached_deposit(1000 + STORAGE_DEPOSIT).build());
+        contract.new(
+            None,
+            accounts(0),
+            accounts(1),
+            U128(1000),
+            None,
+            None,
+            None,
+            None,
+            Some(vec![accounts(2), accounts(3), accounts(4)]),
+            Some(2),
+        );
+
+        testing_env!(get_context(false).predecessor_account_id(accounts(2)).build());
+        assert_eq!(contract.approve_release("1".to_string()), 1);
+        testing_env!(get_context(false).predecessor_account_id(accounts(3)).build());
+        assert_eq!(contract.approve_release("1".to_string()), 2);
+
+        testing_env!(get_context(false).build());
+        contract.release_deposit("1".to_string());
+        assert_eq!(contract.read_item(&"1".to_string()).unwrap().status, Status::PayedOff);
+    }
+
+    #[test]
+    #[should_panic(expected = "the release requires 2 approvals; there are 1")]
+    fn test_release_without_enough_approvals() {
+        let mut contract = setup_contract();
+        testing_env!(get_context(false).attached_deposit(1000 + STORAGE_DEPOSIT).build());
+        contract.new(
+            None,
+            accounts(0),
+            accounts(1),
+            U128(1000),
+            None,
+            None,
+            None,
+            None,
+            Some(vec![accounts(2), accounts(3), accounts(4)]),
+            Some(2),
+        );
+
+        testing_env!(get_context(false).predecessor_account_id(accounts(2)).build());
+        contract.approve_release("1".to_string());
+        testing_env!(get_context(false).predecessor_account_id(accounts(3)).build());
+        contract.approve_release("1".to_string());
+        assert_eq!(contract.revoke_approval("1".to_string()), 1);
+
+        testing_env!(get_context(false).build());
+        contract.release_deposit("1".to_string());
+    }
+
+    #[test]
+    fn test_list_escrows() {
+        let mut contract = setup_contract();
+        for i in 0..3 {
+            testing_env!(get_context(false).attached_deposit(1000 + STORAGE_DEPOSIT).build());
+            contract.new(
+                Some(format!("order{}", i % 2)),
+                accounts(0),
+                accounts(1),
+                U128(1000),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            );
+        }
+        contract.release_deposit("1".to_string());
+
+        assert_eq!(contract.list_escrows_by_funder(accounts(0), None, None).len(), 3);
+        let page = contract.list_escrows_by_beneficiary(accounts(1), Some(1), Some(1));
+        assert_eq!(page.len(), 1);
+        assert!(contract.list_escrows_by_beneficiary(accounts(2), None, None).is_empty());
+
+        let payed_off = contract.list_escrows_by_status(Status::PayedOff, None, None);
+        assert_eq!(payed_off.len(), 1);
+        assert_eq!(payed_off[0].escrow_id, "1");
+        assert_eq!(contract.list_escrows_by_status(Status::Active, None, None).len(), 2);
+
+        let by_ref = contract.list_escrows_by_external_ref("order0".to_string(), None, None);
+        assert_eq!(by_ref.len(), 2);
+        assert_eq!(by_ref[0].external_ref, Some("order0".to_string()));
+
+        contract.remove_item("1".to_string());
+        assert!(contract.get_item("1".to_string()).is_none());
+        assert!(contract.list_escrows_by_status(Status::PayedOff, None, None).is_empty());
+        assert_eq!(contract.list_escrows_by_funder(accounts(0), None, None).len(), 2);
+        assert_eq!(
+            contract
+                .list_escrows_by_external_ref("order0".to_string(), None, None)
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_view_amounts_are_strings() {
+        let mut contract = setup_contract();
+        testing_env!(get_context(false).attached_deposit(1000 + STORAGE_DEPOSIT).build());
+        contract.new(
+            None,
+            accounts(0),
+            accounts(1),
+            U128(1000),
+            Some(milestones()),
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+
+        let view = serde_json::to_value(contract.get_item("1".to_string()).unwrap()).unwrap();
+        assert_eq!(view["agreed_amount"], "1000");
+        assert_eq!(view["current_amount"], "1000");
+        assert_eq!(view["current_fee_percent"], "1000");
+        assert_eq!(view["milestones"][0]["amount"], "300");
+    }
+
+    #[test]
+    fn test_new_generates_unique_ids() {
+        let mut contract = setup_contract();
+        let mut escrow_ids = Vec::new();
+        for _ in 0..3 {
+            testing_env!(get_context(false).attached_deposit(1000 + STORAGE_DEPOSIT).build());
+            //the same external_ref doesn't collide
+            escrow_ids.push(contract.new(
+                Some("order".to_string()),
+                accounts(0),
+                accounts(1),
+                U128(1000),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            ));
+        }
+
+        assert_eq!(escrow_ids, vec!["1", "2", "3"]);
+        assert_eq!(
+            contract.get_item("2".to_string()).unwrap().external_ref,
+            Some("order".to_string())
+        );
+    }
+
+    fn escrow_item_v0(escrow_id: &str) -> EscrowItemV0 {
+        EscrowItemV0 {
+            escrow_id: escrow_id.to_string(),
+            status: Status::Active,
+            funder_account_id: accounts(0),
+            beneficiary_account_id: accounts(1),
+            agreed_amount: 1000,
+            current_amount: 1000,
+            //percent
+            current_fee_percent: 10,
+        }
+    }
+
+    #[test]
+    fn test_read_escrow_item_of_baseline_layout() {
+        //written before the escrows got versioned
+        let bytes = escrow_item_v0("e0").try_to_vec().unwrap();
+        let item = EscrowItem::from(VersionedEscrowItem::try_from_slice(&bytes).unwrap());
+        assert_eq!(item.escrow_id, "e0");
+        assert_eq!(item.status, Status::Active);
+        assert_eq!(item.current_amount, 1000);
+        assert_eq!(item.current_fee_percent, 1_000);
+        assert_eq!(item.storage_payer_id, None);
+        assert_eq!(item.external_ref, Some("e0".to_string()));
+        assert!(item.milestones.is_empty());
+
+        //written back in the latest layout
+        let bytes = VersionedEscrowItem::from(item).try_to_vec().unwrap();
+        assert!(matches!(
+            VersionedEscrowItem::try_from_slice(&bytes).unwrap(),
+            VersionedEscrowItem::V1(_)
+        ));
+    }
+
+    /// writes a state of the layout deployed first with a single escrow, of `escrow_id` chosen by its funder
+    fn write_state_v0(escrow_id: &str) {
+        let mut items: TreeMap<EscrowId, EscrowItemV0> = TreeMap::new(b"t");
+        items.insert(&escrow_id.to_string(), &escrow_item_v0(escrow_id));
+
+        let state = EscrowV0 {
+            owner_id: accounts(0),
+            //percent
+            base_fee_percent: 5,
+            items: TreeMap::try_from_slice(&items.try_to_vec().unwrap()).unwrap(),
+        };
+        env::state_write(&state);
+    }
+
+    #[test]
+    fn test_migrate_state_of_baseline_layout() {
+        testing_env!(get_context(false).build());
+        write_state_v0("e0");
+
+        let mut contract = Escrow::migrate();
+        assert_eq!(contract.get_state_version(), STATE_VERSION);
+        assert_eq!(versioned::read_state_version(), STATE_VERSION);
+        assert_eq!(contract.get_owner_id(), accounts(0));
+        assert_eq!(contract.get_base_fee_percent(), 500);
+        assert_eq!(contract.get_treasury_account_id(), accounts(0));
+        assert_eq!(
+            contract.get_emergency_withdrawal_delay(),
+            Escrow::DEFAULT_EMERGENCY_WITHDRAWAL_DELAY
+        );
+
+        //the escrow gets indexed once the backfill gets to it
+        let escrow_id = "e0".to_string();
+        let ids = |views: Vec<EscrowView>| views.into_iter().map(|view| view.escrow_id).collect::<Vec<_>>();
+        assert!(contract.list_escrows_by_funder(accounts(0), None, None).is_empty());
+        assert!(!contract.index_old_escrows(Some(1)));
+        assert!(contract.index_old_escrows(Some(1)));
+        assert!(contract.index_old_escrows(Some(1)));
+
+        //the escrow is indexed and keeps its fee, in basis points now
+        assert_eq!(
+            ids(contract.list_escrows_by_funder(accounts(0), None, None)),
+            vec![escrow_id.clone()]
+        );
+        assert_eq!(
+            ids(contract.list_escrows_by_beneficiary(accounts(1), None, None)),
+            vec![escrow_id.clone()]
+        );
+        assert_eq!(
+            ids(contract.list_escrows_by_status(Status::Active, None, None)),
+            vec![escrow_id.clone()]
+        );
+        //the escrow_id chosen by the funder serves as the reference
+        assert_eq!(
+            ids(contract.list_escrows_by_external_ref(escrow_id.clone(), None, None)),
+            vec![escrow_id.clone()]
+        );
+        contract.release_deposit(escrow_id.clone());
+        assert_eq!(contract.get_claimable_balance(accounts(1), None).0, 900);
+        assert_eq!(contract.get_accrued_fees(None).0, 100);
+        assert_eq!(
+            ids(contract.list_escrows_by_status(Status::PayedOff, None, None)),
+            vec![escrow_id]
+        );
+    }
+
+    #[test]
+    fn test_old_escrow_updated_before_backfill_gets_indexed() {
+        testing_env!(get_context(false).build());
+        write_state_v0("e0");
+        let mut contract = Escrow::migrate();
+
+        //written back in the latest layout, and indexed along
+        let escrow_id = "e0".to_string();
+        contract.release_deposit(escrow_id.clone());
+        let ids = |views: Vec<EscrowView>| views.into_iter().map(|view| view.escrow_id).collect::<Vec<_>>();
+        assert_eq!(
+            ids(contract.list_escrows_by_funder(accounts(0), None, None)),
+            vec![escrow_id.clone()]
+        );
+        assert_eq!(
+            ids(contract.list_escrows_by_status(Status::PayedOff, None, None)),
+            vec![escrow_id.clone()]
+        );
+
+        //the backfill skips it
+        assert!(contract.index_old_escrows(None));
+        assert_eq!(
+            ids(contract.list_escrows_by_beneficiary(accounts(1), None, None)),
+            vec![escrow_id]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "only owner may call this method")]
+    fn test_index_old_escrows_by_other_account() {
+        testing_env!(get_context(false).build());
+        write_state_v0("e0");
+        let mut contract = Escrow::migrate();
+
+        testing_env!(get_context(false).predecessor_account_id(accounts(1)).build());
+        contract.index_old_escrows(None);
+    }
+
+    #[test]
+    fn test_generated_ids_skip_those_of_old_layout() {
+        testing_env!(get_context(false).build());
+        write_state_v0("1");
+        let mut contract = Escrow::migrate();
+
+        testing_env!(get_context(false).attached_deposit(1000 + STORAGE_DEPOSIT).build());
+        let escrow_id = contract.new(
+            None,
+            accounts(0),
+            accounts(1),
+            U128(1000),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        assert_eq!(escrow_id, "2");
+        assert_eq!(contract.read_item(&"1".to_string()).unwrap().current_fee_percent, 1_000);
+    }
+
+    #[test]
+    fn test_migrate_state_of_latest_layout() {
+        let contract = setup_contract();
+        env::state_write(&contract);
+
+        let contract = Escrow::migrate();
+        assert_eq!(contract.get_state_version(), STATE_VERSION);
+        assert_eq!(contract.get_base_fee_percent(), 1_000);
+    }
+
+    #[test]
+    #[should_panic(expected = "only owner may call this method")]
+    fn test_migrate_by_other_account() {
+        testing_env!(get_context(false).build());
+        write_state_v0("e0");
+
+        testing_env!(get_context(false).predecessor_account_id(accounts(1)).build());
+        Escrow::migrate();
+    }
+
+    #[test]
+    fn test_finished_escrow_records_finished_at() {
+        let mut contract = setup_contract();
+        testing_env!(get_context(false).attached_deposit(1000 + STORAGE_DEPOSIT).build());
+        create_escrow(&mut contract, accounts(0), 1000);
+        assert_eq!(contract.read_item(&"1".to_string()).unwrap().finished_at, None);
+
+        testing_env!(get_context(false).block_timestamp(200).build());
+        contract.release_deposit("1".to_string());
+        assert_eq!(contract.read_item(&"1".to_string()).unwrap().finished_at, Some(200));
+    }
+
+    #[test]
+    fn test_stage_and_deploy_code() {
+        let mut contract = setup_contract();
+        testing_env!(get_context(false).block_timestamp(100).build());
+        let staged_code = contract.stage_code(Base64VecU8(b"code".to_vec()));
+        assert_eq!(contract.get_staged_code(), Some(staged_code));
+        assert_eq!(staged_code.deployable_at, 100 + upgrade::MIN_DEPLOY_DELAY);
+
+        testing_env!(get_context(false).block_timestamp(staged_code.deployable_at).build());
+        contract.deploy_staged_code();
+        assert_eq!(contract.get_staged_code(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "may not be deployed until")]
+    fn test_deploy_code_before_delay() {
+        let mut contract = setup_contract();
+        contract.stage_code(Base64VecU8(b"code".to_vec()));
+        contract.deploy_staged_code();
+    }
+
+    #[test]
+    #[should_panic(expected = "only owner may call this method")]
+    fn test_stage_code_by_other_account() {
+        let mut contract = setup_contract();
+        testing_env!(get_context(false).predecessor_account_id(accounts(1)).build());
+        contract.stage_code(Base64VecU8(b"code".to_vec()));
     }
 }
@@ -0,0 +1,183 @@
+use crate::*;
+use antioquia_common::versioned::{self, read_version, unknown_version, write_version};
+use std::io::{self, Write};
+
+/// the version of the layout of the contract state; stored under a key of its own,
+/// which `migrate` reads, see `versioned::write_state_version`
+pub const STATE_VERSION: u32 = 1;
+
+/// an EscrowItem as it was deployed first, unversioned; `current_fee_percent` is in percent
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct EscrowItemV0 {
+    pub escrow_id: EscrowId,
+    pub status: Status,
+    pub funder_account_id: AccountId,
+    pub beneficiary_account_id: AccountId,
+    pub agreed_amount: Balance,
+    pub current_amount: Balance,
+    pub current_fee_percent: u128,
+}
+
+/// an escrow as stored, in any of its layouts; it gets upgraded to EscrowItem once read
+/// and stored as such once written back
+#[allow(clippy::large_enum_variant)]
+pub enum VersionedEscrowItem {
+    V0(EscrowItemV0),
+    V1(EscrowItem),
+}
+
+impl BorshSerialize for VersionedEscrowItem {
+    fn serialize<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        match self {
+            Self::V0(item) => {
+                write_version(0, writer)?;
+                item.serialize(writer)
+            }
+            Self::V1(item) => {
+                write_version(1, writer)?;
+                item.serialize(writer)
+            }
+        }
+    }
+}
+
+impl BorshDeserialize for VersionedEscrowItem {
+    fn deserialize(buf: &mut &[u8]) -> io::Result<Self> {
+        match read_version(buf)? {
+            0 => Ok(Self::V0(EscrowItemV0::deserialize(buf)?)),
+            1 => Ok(Self::V1(EscrowItem::deserialize(buf)?)),
+            version => Err(unknown_version(version)),
+        }
+    }
+}
+
+impl From<EscrowItem> for VersionedEscrowItem {
+    fn from(item: EscrowItem) -> Self {
+        Self::V1(item)
+    }
+}
+
+impl From<EscrowItemV0> for EscrowItem {
+    fn from(item: EscrowItemV0) -> Self {
+        Self {
+            // the escrows created before had the caller choose their escrow_id, which serves as their reference
+            external_ref: Some(item.escrow_id.clone()),
+            escrow_id: item.escrow_id,
+            status: item.status,
+            funder_account_id: item.funder_account_id,
+            beneficiary_account_id: item.beneficiary_account_id,
+            agreed_amount: item.agreed_amount,
+            current_amount: item.current_amount,
+            current_fee_percent: item.current_fee_percent * fees::BPS_PER_PERCENT,
+            milestones: Vec::new(),
+            // unknown for the escrows created before
+            inserted_at: 0,
+            release_deadline_at: None,
+            arbiter_account_id: None,
+            beneficiaries: Vec::new(),
+            approvers: Vec::new(),
+            approval_threshold: 0,
+            approvals: Vec::new(),
+            settlement_proposal: None,
+            funded_at: None,
+            token_account_id: None,
+            emergency_withdrawal: None,
+            // the contract has paid for the storage
+            storage_payer_id: None,
+            // unknown for the escrows finished before
+            finished_at: None,
+            storage_cost: 0,
+        }
+    }
+}
+
+impl From<VersionedEscrowItem> for EscrowItem {
+    fn from(item: VersionedEscrowItem) -> Self {
+        match item {
+            VersionedEscrowItem::V0(item) => item.into(),
+            VersionedEscrowItem::V1(item) => item,
+        }
+    }
+}
+
+/// the contract state as it was deployed first; `base_fee_percent` is in percent
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct EscrowV0 {
+    pub owner_id: AccountId,
+    pub base_fee_percent: u128,
+    // the layout of a collection doesn't depend on the type of its values
+    pub items: TreeMap<EscrowId, VersionedEscrowItem>,
+}
+
+/// the contract state in any of its layouts
+#[allow(clippy::large_enum_variant)]
+pub enum VersionedEscrow {
+    V0(EscrowV0),
+    V1(Escrow),
+}
+
+impl VersionedEscrow {
+    /// reads the state in the layout of its version, see `versioned::read_state_version`
+    pub fn read() -> Self {
+        let state = versioned::read_state();
+        let result = match versioned::read_state_version() {
+            0 => EscrowV0::try_from_slice(&state).map(Self::V0),
+            1 => Escrow::try_from_slice(&state).map(Self::V1),
+            version => env::panic_str(&format!("the state is of an unknown version {}", version)),
+        };
+        result.unwrap_or_else(|_| env::panic_str("the state doesn't match its version"))
+    }
+
+    pub fn version(&self) -> u32 {
+        match self {
+            Self::V0(_) => 0,
+            Self::V1(_) => STATE_VERSION,
+        }
+    }
+
+    pub fn owner_id(&self) -> &AccountId {
+        match self {
+            Self::V0(escrow) => &escrow.owner_id,
+            Self::V1(escrow) => &escrow.owner_id,
+        }
+    }
+}
+
+impl From<EscrowV0> for Escrow {
+    fn from(escrow: EscrowV0) -> Self {
+        Self {
+            treasury: Treasury::new(escrow.owner_id.clone(), b"a", b"r"),
+            owner_id: escrow.owner_id,
+            proposed_owner_id: None,
+            roles: Roles::new(b"g"),
+            pause_flags: PauseFlags::default(),
+            base_fee_percent: escrow.base_fee_percent * fees::BPS_PER_PERCENT,
+            fee_limits: FeeLimits::default(),
+            fee_schedule: FeeSchedule::new(b"o", b"v"),
+            items: escrow.items,
+            claims: ClaimLedger::new(b"c"),
+            // the escrows deployed first get indexed page by page, see Escrow::index_old_escrows
+            items_by_funder: LookupMap::new(b"f"),
+            items_by_beneficiary: LookupMap::new(b"b"),
+            items_by_status: LookupMap::new(b"s"),
+            emergency_withdrawal_delay: Escrow::DEFAULT_EMERGENCY_WITHDRAWAL_DELAY,
+            storage_accounts: StorageAccounts::new(b"m"),
+            // the escrow_ids taken by then get skipped, see Escrow::next_escrow_id
+            last_escrow_id: 0,
+            items_by_external_ref: LookupMap::new(b"x"),
+            index_backfill: Backfill::Pending { after: None },
+            accepted_tokens: UnorderedSet::new(b"k"),
+            ft_storage_authorized: LookupSet::new(b"p"),
+            refunds: ClaimLedger::new(b"e"),
+        }
+    }
+}
+
+impl From<VersionedEscrow> for Escrow {
+    fn from(state: VersionedEscrow) -> Self {
+        match state {
+            VersionedEscrow::V0(escrow) => escrow.into(),
+            VersionedEscrow::V1(escrow) => escrow,
+        }
+    }
+}
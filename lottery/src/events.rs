@@ -0,0 +1,121 @@
+use crate::{LotteryId, ParticipantStatus};
+use antioquia_common::events::emit_event;
+use antioquia_common::fee_schedule::FeeTier;
+use antioquia_common::pause::Feature;
+use antioquia_common::roles::Role;
+use near_sdk::json_types::{Base58CryptoHash, U128};
+use near_sdk::serde::Serialize;
+use near_sdk::AccountId;
+
+const EVENT_STANDARD: &str = "antioquia_lottery";
+const EVENT_VERSION: &str = "1.0.0";
+
+/// NEP-297 events of the lottery
+#[derive(Serialize)]
+#[serde(
+    crate = "near_sdk::serde",
+    tag = "event",
+    content = "data",
+    rename_all = "snake_case"
+)]
+pub enum LotteryEvent {
+    LotteryCreate {
+        lottery_id: LotteryId,
+        organiser_account_id: AccountId,
+        agreed_prize_amount: U128,
+        fee_percent: U128,
+        external_ref: Option<String>,
+    },
+    ParticipantChange {
+        lottery_id: LotteryId,
+        account_id: AccountId,
+        status: ParticipantStatus,
+    },
+    LotteryWinner {
+        lottery_id: LotteryId,
+        winner_account_id: AccountId,
+    },
+    PrizePayout {
+        lottery_id: LotteryId,
+        winner_account_id: AccountId,
+        amount: U128,
+        fee: U128,
+        fee_percent: U128,
+    },
+    Withdraw {
+        account_id: AccountId,
+        amount: U128,
+    },
+    WithdrawFail {
+        account_id: AccountId,
+        amount: U128,
+    },
+    FeesWithdraw {
+        treasury_account_id: AccountId,
+        amount: U128,
+    },
+    FeesWithdrawFail {
+        amount: U128,
+    },
+    TreasuryChange {
+        treasury_account_id: AccountId,
+    },
+    OwnershipPropose {
+        owner_id: AccountId,
+        proposed_owner_id: AccountId,
+    },
+    OwnershipTransfer {
+        old_owner_id: AccountId,
+        new_owner_id: AccountId,
+    },
+    OwnershipTransferCancel {
+        proposed_owner_id: AccountId,
+    },
+    RoleGrant {
+        account_id: AccountId,
+        role: Role,
+        granted_by: AccountId,
+    },
+    RoleRevoke {
+        account_id: AccountId,
+        role: Role,
+        revoked_by: AccountId,
+    },
+    Pause {
+        feature: Option<Feature>,
+        account_id: AccountId,
+    },
+    Unpause {
+        feature: Option<Feature>,
+        account_id: AccountId,
+    },
+    FeeLimitsChange {
+        min_fee: U128,
+        max_fee: Option<U128>,
+    },
+    FeeTiersChange {
+        tiers: Vec<FeeTier>,
+    },
+    FeeOverrideChange {
+        account_id: AccountId,
+        fee_percent: Option<U128>,
+    },
+    StateMigrate {
+        from_version: u32,
+        to_version: u32,
+    },
+    CodeStage {
+        hash: Base58CryptoHash,
+        deployable_at: u64,
+    },
+    CodeUnstage {},
+    CodeDeploy {
+        hash: Base58CryptoHash,
+    },
+}
+
+impl LotteryEvent {
+    pub fn emit(&self) {
+        emit_event(EVENT_STANDARD, EVENT_VERSION, self);
+    }
+}
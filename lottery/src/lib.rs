@@ -1,12 +1,34 @@
+mod events;
+mod migration;
+
+use antioquia_common::fee_schedule::{FeeSchedule, FeeTier};
+use antioquia_common::fees::{self, FeeLimits, Rounding, HUNDRED_PERCENT_BPS};
+use antioquia_common::index::{self, index_add, index_remove, Backfill, Index};
+use antioquia_common::ledger::ClaimLedger;
+use antioquia_common::pause::{Feature, PauseFlags};
+use antioquia_common::roles::{Role, Roles};
+use antioquia_common::storage::{self, StorageAccounts, StorageBalance, StorageBalanceBounds};
+use antioquia_common::treasury::Treasury;
+use antioquia_common::upgrade::{self, StagedCode};
+use antioquia_common::versioned;
+use events::LotteryEvent;
+use migration::{VersionedLottery, VersionedLotteryItem, STATE_VERSION};
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::TreeMap;
-use near_sdk::{env, log, near_bindgen, require, AccountId, Balance, PanicOnDefault, Promise};
+use near_sdk::collections::{LookupMap, TreeMap};
+use near_sdk::json_types::{Base64VecU8, U128};
+use near_sdk::serde::Serialize;
+use near_sdk::{
+    assert_one_yocto, env, log, near_bindgen, require, AccountId, Balance, Gas, PanicOnDefault,
+    Promise, PromiseResult,
+};
 use std::collections::BTreeMap;
 
-//TODO replace with the proper type - UUID
+const GAS_FOR_WITHDRAW_CALLBACK: Gas = Gas(10_000_000_000_000);
+
+//generated by the contract out of a counter, see next_lottery_id
 type LotteryId = String;
 
-#[derive(BorshDeserialize, BorshSerialize, PartialEq, Debug)]
+#[derive(BorshDeserialize, BorshSerialize, PartialEq, Debug, Clone)]
 pub enum Status {
     Draft,
     Canceled,
@@ -24,7 +46,7 @@ pub enum Status {
     Closed,
 }
 
-#[derive(BorshDeserialize, BorshSerialize, PartialEq, Debug)]
+#[derive(BorshDeserialize, BorshSerialize, PartialEq, Debug, Clone)]
 pub enum PrizeStatus {
     DepositPending,
     DepositFunded,
@@ -33,14 +55,15 @@ pub enum PrizeStatus {
     //PartiallyPayedOffAndReimbursed,
 }
 
-#[derive(BorshDeserialize, BorshSerialize, PartialEq, Debug, Copy, Clone)]
+#[derive(BorshDeserialize, BorshSerialize, Serialize, PartialEq, Debug, Copy, Clone)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
 pub enum ParticipantStatus {
     Active,
     OptedOut,
     Suspended,
 }
 
-#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
+#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault, Clone)]
 pub struct LotteryItem {
     pub lottery_id: LotteryId,
     pub status: Status,
@@ -56,8 +79,15 @@ pub struct LotteryItem {
     pub prize_status: PrizeStatus,
     pub current_fee_percent: u128,
     // pub amount_paid_off: bool,
-    // pub started_at: u128
-    // pub ended_at: u128
+    // nanoseconds, env::block_timestamp(); None for the lotteries created before they got recorded
+    pub started_at: Option<u64>,
+    // set once the prize has been released
+    pub ended_at: Option<u64>,
+    // a reference of the organiser's own, not necessarily unique; the lotteries get indexed by it
+    pub external_ref: Option<String>,
+    // what each account has paid for the storage of the lottery and its participants,
+    // released to it once the lottery gets removed
+    pub storage_costs: BTreeMap<AccountId, Balance>,
 }
 
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault, Copy, Clone)]
@@ -69,24 +99,45 @@ pub struct Participant {
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
 pub struct Lottery {
     pub owner_account_id: AccountId,
+    pub proposed_owner_id: Option<AccountId>,
+    pub roles: Roles,
+    pub pause_flags: PauseFlags,
     pub base_fee_percent: u128,
-    pub items: TreeMap<LotteryId, LotteryItem>,
+    pub fee_limits: FeeLimits,
+    pub fee_schedule: FeeSchedule,
+    //upgraded to the latest layout once read, see read_item
+    pub items: TreeMap<LotteryId, VersionedLotteryItem>,
+    pub claims: ClaimLedger,
+    pub treasury: Treasury,
+    //the storage deposits, NEP-145
+    pub storage_accounts: StorageAccounts,
+    //the last lottery_id generated, see next_lottery_id
+    pub last_lottery_id: u64,
+    pub items_by_external_ref: Index<String, LotteryId>,
+    //the lotteries of the layout deployed first get indexed page by page, see index_old_lotteries
+    pub index_backfill: Backfill<LotteryId>,
 }
 
 #[near_bindgen]
 impl Lottery {
     const MIN_FEE_PERCENT: u128 = 0;
-    const MAX_FEE_PERCENT: u128 = 100;
-    const HUNDRED_PERCENT: u128 = 100;
+    const MAX_FEE_PERCENT: u128 = HUNDRED_PERCENT_BPS;
+    const FEE_ROUNDING: Rounding = Rounding::Down;
+    const DEFAULT_PAGE_LIMIT: u64 = 50;
 
+    /// * `treasury_account_id` - where the fees get withdrawn to; the owner, if it isn't provided
     #[init]
-    pub fn init(_owner_account_id: Option<AccountId>, base_fee_percent: Option<u128>) -> Self {
+    pub fn init(
+        _owner_account_id: Option<AccountId>,
+        base_fee_percent: Option<u128>,
+        treasury_account_id: Option<AccountId>,
+    ) -> Self {
         require!(!env::state_exists(), "Already initialized");
         let owner_account_id = _owner_account_id.unwrap_or(env::signer_account_id());
+        let treasury_account_id = treasury_account_id.unwrap_or_else(|| owner_account_id.clone());
         let base_fee_percent2 = base_fee_percent.unwrap_or(Self::MIN_FEE_PERCENT);
         require!(
-            (base_fee_percent2 >= Self::MIN_FEE_PERCENT)
-                && (base_fee_percent2 <= Self::MAX_FEE_PERCENT),
+            (Self::MIN_FEE_PERCENT..=Self::MAX_FEE_PERCENT).contains(&base_fee_percent2),
             format!(
                 "base_fee_percent must be between {}..{}",
                 &Self::MIN_FEE_PERCENT,
@@ -94,24 +145,277 @@ impl Lottery {
             )
         );
 
-        let items: TreeMap<LotteryId, LotteryItem> = TreeMap::new(b"t");
+        let items: TreeMap<LotteryId, VersionedLotteryItem> = TreeMap::new(b"t");
+        versioned::write_state_version(STATE_VERSION);
         Self {
             owner_account_id,
+            proposed_owner_id: None,
+            roles: Roles::new(b"g"),
+            pause_flags: PauseFlags::default(),
             base_fee_percent: base_fee_percent2,
+            fee_limits: FeeLimits::default(),
+            fee_schedule: FeeSchedule::new(b"o", b"v"),
             items,
+            claims: ClaimLedger::new(b"c"),
+            treasury: Treasury::new(treasury_account_id, b"a", b"r"),
+            storage_accounts: StorageAccounts::new(b"m"),
+            last_lottery_id: 0,
+            items_by_external_ref: LookupMap::new(b"x"),
+            index_backfill: Backfill::Done,
+        }
+    }
+
+    /// upgrades the state of an older layout, once new code has been deployed;
+    /// the lotteries get upgraded one by one, once read
+    ///
+    /// who may call this method
+    ///     * owner
+    ///     * the contract itself
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let state = VersionedLottery::read();
+        let caller = env::predecessor_account_id();
+        require!(
+            &caller == state.owner_account_id() || caller == env::current_account_id(),
+            "only owner may call this method"
+        );
+
+        let from_version = state.version();
+        let lottery = Lottery::from(state);
+        versioned::write_state_version(STATE_VERSION);
+        LotteryEvent::StateMigrate {
+            from_version,
+            to_version: STATE_VERSION,
+        }
+        .emit();
+        lottery
+    }
+
+    /// returns the version of the layout of the state
+    pub fn get_state_version(&self) -> u32 {
+        versioned::read_state_version()
+    }
+
+    /// indexes up to `limit` of the lotteries stored in the layout deployed first, which `migrate` leaves unindexed;
+    /// to be called until it returns true, as the lotteries show up in the lists only once indexed
+    ///
+    /// who may call this method
+    ///     * owner
+    pub fn index_old_lotteries(&mut self, limit: Option<u64>) -> bool {
+        require!(
+            self.owner_account_id == env::predecessor_account_id(),
+            "only owner may call this method"
+        );
+
+        let lottery_ids = self
+            .index_backfill
+            .next_page(&self.items, limit.unwrap_or(Self::DEFAULT_PAGE_LIMIT));
+        for lottery_id in lottery_ids {
+            if let Some(VersionedLotteryItem::V0(lottery)) = self.items.get(&lottery_id) {
+                //written back in the latest layout, which indexes it
+                self.save_item(&lottery.into());
+            }
+        }
+
+        self.index_backfill.is_done()
+    }
+
+    /// stages new code of the contract, in place of the code staged before, if any;
+    /// it may be deployed once upgrade::MIN_DEPLOY_DELAY has passed, giving the users time to check it
+    ///
+    /// who may call this method
+    ///     * owner
+    pub fn stage_code(&mut self, code: Base64VecU8) -> StagedCode {
+        require!(
+            self.owner_account_id == env::predecessor_account_id(),
+            "only owner may call this method"
+        );
+
+        let staged_code = upgrade::stage(&code.0);
+        LotteryEvent::CodeStage {
+            hash: staged_code.hash,
+            deployable_at: staged_code.deployable_at,
+        }
+        .emit();
+        staged_code
+    }
+
+    /// removes the staged code, if any
+    ///
+    /// who may call this method
+    ///     * owner
+    pub fn unstage_code(&mut self) -> bool {
+        require!(
+            self.owner_account_id == env::predecessor_account_id(),
+            "only owner may call this method"
+        );
+
+        let unstaged = upgrade::unstage();
+        if unstaged {
+            LotteryEvent::CodeUnstage {}.emit();
+        }
+
+        unstaged
+    }
+
+    /// deploys the staged code, once the delay has passed, and migrates the state to its layout
+    ///
+    /// who may call this method
+    ///     * owner
+    pub fn deploy_staged_code(&mut self) -> Promise {
+        require!(
+            self.owner_account_id == env::predecessor_account_id(),
+            "only owner may call this method"
+        );
+
+        let (staged_code, promise) = upgrade::deploy();
+        LotteryEvent::CodeDeploy {
+            hash: staged_code.hash,
         }
+        .emit();
+        promise
+    }
+
+    /// returns the staged code, if any: its sha256 hash, to check it against the source, and when it may be deployed
+    pub fn get_staged_code(&self) -> Option<StagedCode> {
+        upgrade::staged()
     }
 
-    ///creates a new lottery
-    ///and deposits funds
+    /// returns the Id of the owner
+    pub fn get_owner_id(&self) -> AccountId {
+        self.owner_account_id.clone()
+    }
+
+    /// returns the account proposed to become the owner, if any
+    pub fn get_proposed_owner_id(&self) -> Option<AccountId> {
+        self.proposed_owner_id.clone()
+    }
+
+    /// the first step of transferring the ownership;
+    /// the proposed account becomes the owner once it accepts it
+    pub fn propose_owner(&mut self, proposed_owner_id: AccountId) {
+        require!(
+            self.owner_account_id == env::predecessor_account_id(),
+            "only owner may call this method"
+        );
+        require!(
+            proposed_owner_id != self.owner_account_id,
+            "the proposed account is the owner already"
+        );
+
+        self.proposed_owner_id = Some(proposed_owner_id.clone());
+        LotteryEvent::OwnershipPropose {
+            owner_id: self.owner_account_id.clone(),
+            proposed_owner_id,
+        }
+        .emit();
+    }
+
+    /// the second step of transferring the ownership, called by the proposed account
+    pub fn accept_ownership(&mut self) {
+        let caller = env::predecessor_account_id();
+        require!(
+            self.proposed_owner_id.as_ref() == Some(&caller),
+            "only the proposed owner may call this method"
+        );
+
+        let old_owner_id = std::mem::replace(&mut self.owner_account_id, caller.clone());
+        self.proposed_owner_id = None;
+        LotteryEvent::OwnershipTransfer {
+            old_owner_id,
+            new_owner_id: caller,
+        }
+        .emit();
+    }
+
+    /// withdraws the proposal; the owner stays the same
+    pub fn cancel_ownership_transfer(&mut self) {
+        require!(
+            self.owner_account_id == env::predecessor_account_id(),
+            "only owner may call this method"
+        );
+
+        match self.proposed_owner_id.take() {
+            Some(proposed_owner_id) => {
+                LotteryEvent::OwnershipTransferCancel { proposed_owner_id }.emit();
+            }
+            None => env::panic_str("there's no ownership transfer to cancel"),
+        }
+    }
+
+    /// returns the minimum absolute fee of the lotteries
+    pub fn get_min_fee(&self) -> U128 {
+        U128(self.fee_limits.min_fee)
+    }
+
+    /// returns the maximum absolute fee of the lotteries; None if there's no maximum
+    pub fn get_max_fee(&self) -> Option<U128> {
+        self.fee_limits.max_fee.map(U128)
+    }
+
+    /// sets the absolute bounds the fee of the lotteries gets clamped by
+    pub fn set_fee_limits(&mut self, min_fee: U128, max_fee: Option<U128>) {
+        self.require_role(Role::FeeManager);
+
+        self.fee_limits = FeeLimits::new(min_fee.0, max_fee.map(|f| f.0));
+        LotteryEvent::FeeLimitsChange { min_fee, max_fee }.emit();
+    }
+
+    /// returns the fee tiers, by the cumulative volume of the organisers
+    pub fn get_fee_tiers(&self) -> Vec<FeeTier> {
+        self.fee_schedule.tiers().to_vec()
+    }
+
+    /// replaces the fee tiers; they have to be sorted by `min_volume`, ascending
+    pub fn set_fee_tiers(&mut self, tiers: Vec<FeeTier>) {
+        self.require_role(Role::FeeManager);
+
+        self.fee_schedule.set_tiers(tiers.clone());
+        LotteryEvent::FeeTiersChange { tiers }.emit();
+    }
+
+    /// returns the fee of an organiser that overrides the tiers, if any
+    pub fn get_fee_override(&self, account_id: AccountId) -> Option<u128> {
+        self.fee_schedule.override_of(&account_id)
+    }
+
+    /// sets the fee of an organiser regardless of the tiers, such as a discount or 0 for an exempt one;
+    /// None removes the override
+    pub fn set_fee_override(&mut self, account_id: AccountId, fee_percent: Option<u128>) {
+        self.require_role(Role::FeeManager);
+
+        self.fee_schedule.set_override(&account_id, fee_percent);
+        LotteryEvent::FeeOverrideChange {
+            account_id,
+            fee_percent: fee_percent.map(U128),
+        }
+        .emit();
+    }
+
+    /// returns the cumulative volume of an organiser
+    pub fn get_volume(&self, account_id: AccountId) -> U128 {
+        U128(self.fee_schedule.volume_of(&account_id))
+    }
+
+    /// returns the fee, in basis points, a new lottery of an organiser would get
+    pub fn get_fee_percent_of(&self, account_id: AccountId) -> u128 {
+        self.fee_schedule
+            .fee_bps_of(&account_id, self.base_fee_percent)
+    }
+
+    ///creates a new lottery, deposits funds
+    ///and returns the lottery_id, generated by the contract
+    /// * `external_ref` - optional; a reference of the organiser's own, see `list_lotteries_by_external_ref`
     #[payable]
+    #[allow(clippy::new_ret_no_self)]
     pub fn new(
         &mut self,
-        lottery_id: LotteryId,
+        external_ref: Option<String>,
         organiser_account_id: AccountId,
-        agreed_prize_amount: Balance,
-        current_fee_percent: Option<u128>,
-    ) -> Option<LotteryId> {
+        agreed_prize_amount: U128,
+    ) -> LotteryId {
+        self.pause_flags.require_not_paused(Some(Feature::NewDeals));
+        let agreed_prize_amount = agreed_prize_amount.0;
         require!(
             agreed_prize_amount > 0,
             "agreed_prize_amount must be greater than 0"
@@ -125,53 +429,88 @@ impl Lottery {
         //TODO may not be needed
         // require!(organiser_account_id == env::predecessor_account_id(), "'organiser_account_id' argument must match the account id this method is being called from");
 
-        if !self.items.contains_key(&lottery_id) {
-            require!(
-                agreed_prize_amount == attached_deposit_amount,
-                format!(
-                    "agreed_prize_amount and attached_deposit_amount must be equal: {} and {}",
-                    agreed_prize_amount, attached_deposit_amount
-                )
-            );
+        //the fee is that of the organiser at the moment
+        let current_fee_percent = self.get_fee_percent_of(organiser_account_id.clone());
 
-            //FIXME: generate prefixes dynamically for near_sdk::TreeMap
-            // let pts: TreeMap<AccountId, Participant> = TreeMap::new(lottery_id.as_bytes());
-
-            let pts: BTreeMap<AccountId, Participant> = BTreeMap::new();
-            let new_item = LotteryItem {
-                lottery_id: lottery_id.clone(),
-                agreed_prize_amount,
-                status: Status::New,
-                prize_status: PrizeStatus::DepositFunded,
-                organiser_account_id,
-                current_fee_percent: current_fee_percent.unwrap_or(self.base_fee_percent),
-                current_balance: attached_deposit_amount,
-                participants: pts,
-                winner: None,
-            };
+        //the storage of the lottery is paid for on top of the prize
+        //or out of the storage deposit of the caller, if registered
+        require!(
+            agreed_prize_amount <= attached_deposit_amount,
+            format!(
+                "agreed_prize_amount must be equal to or less than attached_deposit_amount: {} and {}",
+                agreed_prize_amount, attached_deposit_amount
+            )
+        );
+        let initial_storage_usage = env::storage_usage();
 
-            self.items.insert(&lottery_id.clone(), &new_item);
-            Some(lottery_id)
-        } else {
-            log!(
-                "lottery_id '{}' already exists; generate a new one",
-                lottery_id
+        //FIXME: generate prefixes dynamically for near_sdk::TreeMap
+        // let pts: TreeMap<AccountId, Participant> = TreeMap::new(lottery_id.as_bytes());
+
+        let lottery_id = self.next_lottery_id();
+        let pts: BTreeMap<AccountId, Participant> = BTreeMap::new();
+        let storage_payer_id = env::predecessor_account_id();
+        let mut new_item = LotteryItem {
+            lottery_id: lottery_id.clone(),
+            agreed_prize_amount,
+            status: Status::New,
+            prize_status: PrizeStatus::DepositFunded,
+            organiser_account_id,
+            current_fee_percent,
+            current_balance: agreed_prize_amount,
+            participants: pts,
+            winner: None,
+            started_at: Some(env::block_timestamp()),
+            ended_at: None,
+            external_ref,
+            //the cost gets recorded once known, taking no more storage
+            storage_costs: BTreeMap::from([(storage_payer_id.clone(), 0)]),
+        };
+
+        self.save_item(&new_item);
+        if let Some(external_ref) = &new_item.external_ref {
+            index_add(
+                &mut self.items_by_external_ref,
+                b"x",
+                external_ref,
+                &lottery_id,
             );
-            None
         }
+        let cost = self.storage_accounts.charge(
+            &storage_payer_id,
+            attached_deposit_amount,
+            agreed_prize_amount,
+            initial_storage_usage,
+        );
+        new_item.storage_costs.insert(storage_payer_id, cost);
+        self.save_item(&new_item);
+
+        LotteryEvent::LotteryCreate {
+            lottery_id: lottery_id.clone(),
+            organiser_account_id: new_item.organiser_account_id,
+            agreed_prize_amount: U128(agreed_prize_amount),
+            fee_percent: U128(new_item.current_fee_percent),
+            external_ref: new_item.external_ref,
+        }
+        .emit();
+        lottery_id
     }
 
+    ///adds a participant to a lottery;
+    ///the storage of the participant is paid for out of the storage deposit of the caller, if registered,
+    ///otherwise out of the attached deposit
+    #[payable]
     pub fn add_participant(
         &mut self,
         lottery_id: LotteryId,
         participant_account_id: AccountId,
     ) -> Option<AccountId> {
-        let mut lottery = self.items.get(&lottery_id).unwrap();
-        let authoriz_cond = (self.owner_account_id == env::predecessor_account_id())
+        self.pause_flags.require_not_paused(Some(Feature::NewDeals));
+        let mut lottery = self.read_item(&lottery_id);
+        let authoriz_cond = self.has_role(env::predecessor_account_id(), Role::Operator)
             || (lottery.organiser_account_id == env::predecessor_account_id());
         require!(
             authoriz_cond,
-            "only organiser or owner of this lottery may call this method"
+            "only organiser or operator of this lottery may call this method"
         );
 
         if lottery.participants.contains_key(&participant_account_id) {
@@ -179,8 +518,10 @@ impl Lottery {
                 "participant with account_id '{}' already exists",
                 participant_account_id
             );
+            storage::refund(&env::predecessor_account_id(), env::attached_deposit());
             None
         } else {
+            let initial_storage_usage = env::storage_usage();
             let new_pt = Participant {
                 status: ParticipantStatus::Active,
             };
@@ -188,12 +529,32 @@ impl Lottery {
             lottery
                 .participants
                 .insert(participant_account_id.clone(), new_pt);
+            //the cost gets recorded once known, taking no more storage
+            let storage_payer_id = env::predecessor_account_id();
+            let paid = *lottery
+                .storage_costs
+                .entry(storage_payer_id.clone())
+                .or_insert(0);
 
             //re-insert the current lottery item
             //this is required in order make the collection update itself
-            self.items.insert(&lottery_id, &lottery);
+            self.save_item(&lottery);
+            let cost = self.storage_accounts.charge(
+                &storage_payer_id,
+                env::attached_deposit(),
+                0,
+                initial_storage_usage,
+            );
+            lottery.storage_costs.insert(storage_payer_id, paid + cost);
+            self.save_item(&lottery);
 
-            Some(participant_account_id.clone())
+            LotteryEvent::ParticipantChange {
+                lottery_id,
+                account_id: participant_account_id.clone(),
+                status: new_pt.status,
+            }
+            .emit();
+            Some(participant_account_id)
         }
     }
 
@@ -202,16 +563,17 @@ impl Lottery {
         lottery_id: LotteryId,
         participant_account_id: AccountId,
     ) {
-        let mut lottery = self.items.get(&lottery_id).unwrap();
+        self.pause_flags.require_not_paused(None);
+        let mut lottery = self.read_item(&lottery_id);
 
-        let authoriz_cond = (self.owner_account_id == env::predecessor_account_id())
+        let authoriz_cond = self.has_role(env::predecessor_account_id(), Role::Operator)
             || (lottery.organiser_account_id == env::predecessor_account_id());
         require!(
             authoriz_cond,
-            "only organiser or owner of this lottery may call this method"
+            "only organiser or operator of this lottery may call this method"
         );
 
-        let mut pt = lottery
+        let pt = lottery
             .participants
             .get_mut(&participant_account_id)
             .unwrap();
@@ -219,27 +581,34 @@ impl Lottery {
 
         //re-insert the current lottery item
         //this is required in order make the collection update itself
-        self.items.insert(&lottery_id, &lottery);
+        self.save_item(&lottery);
+
+        LotteryEvent::ParticipantChange {
+            lottery_id,
+            account_id: participant_account_id,
+            status: ParticipantStatus::OptedOut,
+        }
+        .emit();
     }
 
     //TODO: can be improved
     pub fn pick_random_winner(&mut self, lottery_id: LotteryId) -> AccountId {
+        self.pause_flags.require_not_paused(None);
         const MIDDLE: usize = 16;
 
-        let mut lottery = self.items.get(&lottery_id).unwrap();
-        let authoriz_cond = (self.owner_account_id == env::predecessor_account_id())
+        let mut lottery = self.read_item(&lottery_id);
+        let authoriz_cond = self.has_role(env::predecessor_account_id(), Role::Operator)
             || (lottery.organiser_account_id == env::predecessor_account_id());
         require!(
             authoriz_cond,
-            "only organiser or owner of this lottery may call this method"
+            "only organiser or operator of this lottery may call this method"
         );
 
-        let wn = lottery.winner;
         require!(
-            wn.is_none(),
+            lottery.winner.is_none(),
             format!(
                 "winner had already been chosen before: {}",
-                wn.clone().unwrap()
+                lottery.winner.as_ref().map_or("", |winner| winner.as_str())
             )
         );
 
@@ -251,7 +620,7 @@ impl Lottery {
             .collect();
 
         require!(
-            account_ids.len() > 0,
+            !account_ids.is_empty(),
             "the amount of active participants has to be greater than zero"
         );
 
@@ -267,10 +636,71 @@ impl Lottery {
 
         //re-insert the current lottery item
         //this is required in order make the collection update itself
-        self.items.insert(&lottery_id, &lottery);
+        self.save_item(&lottery);
+
+        LotteryEvent::LotteryWinner {
+            lottery_id,
+            winner_account_id: rnd_account_id.clone(),
+        }
+        .emit();
         rnd_account_id.clone()
     }
 
+    /// reads a lottery, upgrading it to the latest layout
+    fn read_item(&self, lottery_id: &LotteryId) -> LotteryItem {
+        let lottery = self.items.get(lottery_id);
+        require!(
+            lottery.is_some(),
+            format!("lottery_id '{}' not found", lottery_id)
+        );
+        LotteryItem::from(lottery.unwrap())
+    }
+
+    /// generates a unique lottery_id out of a counter;
+    /// the ids chosen by the organisers before are skipped
+    fn next_lottery_id(&mut self) -> LotteryId {
+        loop {
+            self.last_lottery_id += 1;
+            let lottery_id = self.last_lottery_id.to_string();
+            if !self.items.contains_key(&lottery_id) {
+                return lottery_id;
+            }
+        }
+    }
+
+    /// inserts or updates a lottery, in the latest layout;
+    /// one of the layout deployed first gets indexed along, as it isn't yet
+    fn save_item(&mut self, lottery: &LotteryItem) {
+        let prev_item = self
+            .items
+            .insert(&lottery.lottery_id, &lottery.clone().into());
+        if let (Some(VersionedLotteryItem::V0(_)), Some(external_ref)) =
+            (prev_item, &lottery.external_ref)
+        {
+            index_add(
+                &mut self.items_by_external_ref,
+                b"x",
+                external_ref,
+                &lottery.lottery_id,
+            );
+        }
+    }
+
+    /// removes a lottery, along with it from the index; one of the layout deployed first isn't indexed
+    /// until index_old_lotteries gets to it
+    fn delete_item(&mut self, lottery: &LotteryItem) {
+        let prev_item = self.items.remove(&lottery.lottery_id);
+        if let (Some(VersionedLotteryItem::V1(_)), Some(external_ref)) =
+            (prev_item, &lottery.external_ref)
+        {
+            index_remove(
+                &mut self.items_by_external_ref,
+                external_ref,
+                &lottery.lottery_id,
+            );
+        }
+    }
+
     // returns random u8 number (0-254)
     fn random_u8(&self, index: usize) -> u8 {
         *env::random_seed().get(index).unwrap()
@@ -278,39 +708,30 @@ impl Lottery {
 
     // returns random number from 0 to max
     fn random_in_range(&self, index: usize, max: usize) -> u32 {
-        let rand_divider = 256 as f64 / (max + 1) as f64;
+        let rand_divider = 256_f64 / (max + 1) as f64;
         let result = self.random_u8(index) as f64 / rand_divider;
         result as u32
     }
 
     pub fn get_lottery(&self, lottery_id: LotteryId) -> BTreeMap<String, String> {
-        let lottery = self.items.get(&lottery_id).unwrap();
+        let lottery = self.read_item(&lottery_id);
         let mut tree: BTreeMap<String, String> = BTreeMap::new();
-        tree.insert(String::from("lottery_id"), String::from(lottery_id.clone()));
+        tree.insert(String::from("lottery_id"), lottery_id.clone());
 
-        tree.insert(
-            String::from("organiser_account_id"),
-            String::from(lottery_id),
-        );
+        tree.insert(String::from("organiser_account_id"), lottery_id);
 
-        tree.insert(
-            String::from("status"),
-            String::from(format!("{:?}", lottery.status)),
-        );
+        tree.insert(String::from("status"), format!("{:?}", lottery.status));
 
         tree.insert(
             String::from("agreed_prize_amount"),
-            String::from(lottery.agreed_prize_amount.to_string()),
+            lottery.agreed_prize_amount.to_string(),
         );
 
-        tree.insert(
-            String::from("status"),
-            String::from(format!("{:?}", lottery.status)),
-        );
+        tree.insert(String::from("status"), format!("{:?}", lottery.status));
 
         tree.insert(
             String::from("prize_status"),
-            String::from(format!("{:?}", lottery.prize_status)),
+            format!("{:?}", lottery.prize_status),
         );
 
         let winner_key = String::from("winner_account_id");
@@ -322,12 +743,12 @@ impl Lottery {
 
         tree.insert(
             String::from("fee_percent"),
-            String::from(lottery.current_fee_percent.to_string()),
+            lottery.current_fee_percent.to_string(),
         );
 
         tree.insert(
             String::from("total_participants"),
-            String::from(format!("{:?}", lottery.participants.len())),
+            format!("{:?}", lottery.participants.len()),
         );
 
         let ap_c = lottery
@@ -336,92 +757,836 @@ impl Lottery {
             .filter(|(_, v)| v.status == ParticipantStatus::Active)
             .count();
 
+        tree.insert(String::from("active_participants"), format!("{:?}", ap_c));
+
+        let timestamp = |t: Option<u64>| t.map_or(String::from("(null)"), |t| t.to_string());
+        tree.insert(String::from("started_at"), timestamp(lottery.started_at));
+        tree.insert(String::from("ended_at"), timestamp(lottery.ended_at));
         tree.insert(
-            String::from("active_participants"),
-            String::from(format!("{:?}", ap_c)),
+            String::from("external_ref"),
+            lottery.external_ref.unwrap_or(String::from("(null)")),
         );
 
         tree
     }
 
+    #[allow(dead_code)]
     fn get_participant(
         &self,
         lottery_id: LotteryId,
         participant_account_id: AccountId,
     ) -> Option<Participant> {
-        match self
-            .items
-            .get(&lottery_id)
-            .unwrap()
+        self.read_item(&lottery_id)
             .participants
             .get(&participant_account_id)
-        {
-            Some(&pt) => Some(pt),
-            None => None,
-        }
+            .copied()
+    }
+
+    /// returns the ids of the lotteries created with an external reference,
+    /// `limit` of them starting from `from_index`
+    pub fn list_lotteries_by_external_ref(
+        &self,
+        external_ref: String,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<LotteryId> {
+        index::index_page(
+            &self.items_by_external_ref,
+            &external_ref,
+            from_index.unwrap_or(0),
+            limit.unwrap_or(Self::DEFAULT_PAGE_LIMIT),
+        )
     }
 
     pub fn get_winner(&self, lottery_id: LotteryId) -> Option<AccountId> {
-        self.items.get(&lottery_id).unwrap().winner
+        self.read_item(&lottery_id).winner
     }
 
     //releases the prize to the winner
-    pub fn release_prize_to_winner(&mut self, lottery_id: LotteryId) -> (Balance, Balance) {
-        let mut lottery = self.items.get(&lottery_id).unwrap();
-        let authoriz_cond = (self.owner_account_id == env::predecessor_account_id())
+    pub fn release_prize_to_winner(&mut self, lottery_id: LotteryId) -> (U128, U128) {
+        self.pause_flags.require_not_paused(Some(Feature::Payouts));
+        let mut lottery = self.read_item(&lottery_id);
+        let authoriz_cond = self.has_role(env::predecessor_account_id(), Role::Operator)
             || (lottery.organiser_account_id == env::predecessor_account_id());
         require!(
             authoriz_cond,
-            "only organiser or owner of this lottery may call this method"
+            "only organiser or operator of this lottery may call this method"
+        );
+        require!(
+            lottery.winner.is_some(),
+            format!(
+                "no winner has been picked yet for lottery_id '{}'",
+                lottery_id
+            )
         );
 
-        let amount_for_winner = lottery.agreed_prize_amount / Self::HUNDRED_PERCENT
-            * (Self::HUNDRED_PERCENT - lottery.current_fee_percent);
-        let amount_for_owner = lottery.agreed_prize_amount - amount_for_winner;
+        require!(
+            lottery.current_balance >= lottery.agreed_prize_amount,
+            format!(
+                "current_balance ({}) must be equal to or greater than the prize ({});",
+                lottery.current_balance, lottery.agreed_prize_amount
+            )
+        );
 
-        //due to a potential rounding error,
-        //verify that there'll be enough of the funds
-        let amounts_sum = amount_for_winner + amount_for_owner;
-        let calc_cond = lottery.current_balance >= amounts_sum;
-        require!(calc_cond, format!("current_balance ({}) must be equal to or greater than the sum of the amounts to be released ({});", lottery.current_balance, amounts_sum));
+        let fees::FeeSplit {
+            net: amount_for_winner,
+            fee: amount_for_owner,
+        } = fees::split_clamped(
+            lottery.agreed_prize_amount,
+            lottery.current_fee_percent,
+            &self.fee_limits,
+            Self::FEE_ROUNDING,
+        );
 
-        let winner_account_id = lottery.winner.as_ref().unwrap();
+        let winner_account_id = lottery
+            .winner
+            .as_ref()
+            .expect("the winner has been checked");
 
-        //send funds to the winner
-        let p1 = Promise::new(winner_account_id.clone()).transfer(amount_for_winner);
+        //credit the funds to the winner, who then withdraws them
+        self.claims
+            .credit(winner_account_id, &None, amount_for_winner);
         lottery.current_balance -= amount_for_winner;
-        log!(
-            "releasing '{}' to winner '{}'; lottery_id '{}'",
-            amount_for_winner,
-            winner_account_id,
-            lottery_id
-        );
 
-        //send the fees to the owner
-        let p2 = Promise::new(self.owner_account_id.clone()).transfer(amount_for_owner);
-        p1.then(p2);
-        //FIXME verify that _p1 has returned successfully
+        //keep the fees in the treasury
+        self.treasury.accrue(&None, amount_for_owner);
         lottery.current_balance -= amount_for_owner;
-        log!(
-            "[lottery_id '{}'] sending commission of '{}' ({}%) to owner_account_id '{}'",
-            lottery_id,
-            amount_for_owner,
-            lottery.current_fee_percent,
-            self.owner_account_id
-        );
+
+        LotteryEvent::PrizePayout {
+            lottery_id: lottery_id.clone(),
+            winner_account_id: winner_account_id.clone(),
+            amount: U128(amount_for_winner),
+            fee: U128(amount_for_owner),
+            fee_percent: U128(lottery.current_fee_percent),
+        }
+        .emit();
 
         lottery.prize_status = PrizeStatus::WinnerPayedOff;
+        lottery.ended_at = Some(env::block_timestamp());
+        self.fee_schedule
+            .add_volume(&lottery.organiser_account_id, lottery.agreed_prize_amount);
 
         //re-insert the current lottery item
         //this is required in order make the collection update itself
-        self.items.insert(&lottery_id, &lottery);
+        self.save_item(&lottery);
+
+        (U128(amount_for_winner), U128(amount_for_owner))
+    }
+
+    /// removes a lottery whose prize has been released, giving the freed storage back to the ones
+    /// who have paid for it, though no more than each of them has paid
+    ///
+    /// who may call this method
+    ///     * organiser
+    ///     * operator
+    pub fn remove_lottery(&mut self, lottery_id: LotteryId) {
+        self.pause_flags.require_not_paused(None);
+        let lottery = self.read_item(&lottery_id);
+        let authoriz_cond = self.has_role(env::predecessor_account_id(), Role::Operator)
+            || (lottery.organiser_account_id == env::predecessor_account_id());
+        require!(
+            authoriz_cond,
+            "only organiser or operator of this lottery may call this method"
+        );
+        require!(
+            lottery.prize_status == PrizeStatus::WinnerPayedOff,
+            format!(
+                "the prize of lottery_id '{}' hasn't been released, therefore it may not be removed",
+                lottery_id
+            )
+        );
 
-        (amount_for_winner, amount_for_owner)
+        let initial_storage_usage = env::storage_usage();
+        self.delete_item(&lottery);
+
+        //the contract has paid for what the lottery has taken on top, such as the winner
+        let mut refund = storage::storage_refund_since(initial_storage_usage);
+        for (payer, cost) in lottery.storage_costs.iter() {
+            let released = refund.min(*cost);
+            self.storage_accounts.release(payer, released);
+            refund -= released;
+        }
+    }
+
+    /// withdraws `amount`, or all if it isn't provided, of the funds credited to the caller
+    /// by the prize payouts; if the transfer fails, the funds get credited back
+    #[payable]
+    pub fn withdraw(&mut self, amount: Option<U128>) -> Promise {
+        assert_one_yocto();
+        self.pause_flags.require_not_paused(Some(Feature::Payouts));
+        let account_id = env::predecessor_account_id();
+        let amount = self.claims.debit(&account_id, &None, amount.map(|a| a.0));
+        LotteryEvent::Withdraw {
+            account_id: account_id.clone(),
+            amount: U128(amount),
+        }
+        .emit();
+
+        Promise::new(account_id.clone()).transfer(amount).then(
+            Self::ext(env::current_account_id())
+                .with_static_gas(GAS_FOR_WITHDRAW_CALLBACK)
+                .on_withdraw(account_id, U128(amount)),
+        )
+    }
+
+    /// callback of `withdraw`; if the transfer has failed, the funds get credited back
+    #[private]
+    pub fn on_withdraw(&mut self, account_id: AccountId, amount: U128) -> bool {
+        if let PromiseResult::Successful(_) = env::promise_result(0) {
+            return true;
+        }
+
+        self.claims.credit(&account_id, &None, amount.0);
+        LotteryEvent::WithdrawFail { account_id, amount }.emit();
+        false
+    }
+
+    /// grants a role to an account; the owner has every role implicitly
+    ///
+    /// who may call this method
+    ///     * owner
+    ///     * super admin, except for the super admin role
+    pub fn grant_role(&mut self, account_id: AccountId, role: Role) -> bool {
+        let caller = env::predecessor_account_id();
+        let granted = self
+            .roles
+            .grant(&self.owner_account_id, &caller, &account_id, role);
+        if granted {
+            LotteryEvent::RoleGrant {
+                account_id,
+                role,
+                granted_by: caller,
+            }
+            .emit();
+        }
+
+        granted
+    }
+
+    /// revokes a role from an account; who may call this method, the same as of `grant_role`
+    pub fn revoke_role(&mut self, account_id: AccountId, role: Role) -> bool {
+        let caller = env::predecessor_account_id();
+        let revoked = self
+            .roles
+            .revoke(&self.owner_account_id, &caller, &account_id, role);
+        if revoked {
+            LotteryEvent::RoleRevoke {
+                account_id,
+                role,
+                revoked_by: caller,
+            }
+            .emit();
+        }
+
+        revoked
+    }
+
+    pub fn has_role(&self, account_id: AccountId, role: Role) -> bool {
+        self.roles
+            .has_role(&self.owner_account_id, &account_id, role)
+    }
+
+    fn set_paused(&mut self, feature: Option<Feature>, paused: bool) -> bool {
+        self.require_role(Role::Pauser);
+        let changed = self.pause_flags.set(feature, paused);
+        if changed {
+            let account_id = env::predecessor_account_id();
+            match paused {
+                true => LotteryEvent::Pause {
+                    feature,
+                    account_id,
+                }
+                .emit(),
+                false => LotteryEvent::Unpause {
+                    feature,
+                    account_id,
+                }
+                .emit(),
+            }
+        }
+
+        changed
+    }
+
+    fn require_role(&self, role: Role) {
+        self.roles
+            .require_role(&self.owner_account_id, &env::predecessor_account_id(), role);
+    }
+
+    /// pauses `feature` or, if it's None, the whole contract;
+    /// pausing the payouts stops the withdrawals of the credited funds as well
+    pub fn pause(&mut self, feature: Option<Feature>) -> bool {
+        self.set_paused(feature, true)
+    }
+
+    pub fn unpause(&mut self, feature: Option<Feature>) -> bool {
+        self.set_paused(feature, false)
+    }
+
+    /// whether `feature` or, if it's None, the whole contract is paused
+    pub fn is_paused(&self, feature: Option<Feature>) -> bool {
+        self.pause_flags.is_paused(feature)
+    }
+
+    pub fn get_paused_features(&self) -> Vec<Feature> {
+        self.pause_flags.paused_features()
+    }
+
+    /// deposits the attached funds for the storage of `account_id`, the caller by default, registering it if need be;
+    /// if `registration_only`, just what the registration costs gets deposited and the rest refunded
+    #[payable]
+    pub fn storage_deposit(
+        &mut self,
+        account_id: Option<AccountId>,
+        registration_only: Option<bool>,
+    ) -> StorageBalance {
+        let account_id = account_id.unwrap_or_else(env::predecessor_account_id);
+        let (balance, refund) = self.storage_accounts.deposit(
+            &account_id,
+            env::attached_deposit(),
+            registration_only.unwrap_or(false),
+        );
+
+        storage::refund(&env::predecessor_account_id(), refund);
+        balance
+    }
+
+    /// withdraws `amount` of the available storage deposit of the caller; all of it, if amount isn't provided
+    #[payable]
+    pub fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let (balance, amount) = self
+            .storage_accounts
+            .withdraw(&account_id, amount.map(|a| a.0));
+        storage::refund(&account_id, amount);
+        balance
+    }
+
+    /// unregisters the caller and refunds its whole storage deposit, unless it has paid for
+    /// the storage of lotteries or participants out of it; that storage gets released
+    /// once the lotteries get removed, see remove_lottery;
+    /// returns false if the caller isn't registered
+    #[payable]
+    pub fn storage_unregister(&mut self, force: Option<bool>) -> bool {
+        assert_one_yocto();
+        require!(!force.unwrap_or(false), "force isn't supported");
+
+        let account_id = env::predecessor_account_id();
+        match self.storage_accounts.unregister(&account_id) {
+            Some(total) => {
+                storage::refund(&account_id, total);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
+        self.storage_accounts.balance_of(&account_id)
+    }
+
+    pub fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+        StorageAccounts::bounds()
+    }
+
+    /// returns the account the fees get withdrawn to
+    pub fn get_treasury_account_id(&self) -> AccountId {
+        self.treasury.account_id.clone()
+    }
+
+    pub fn set_treasury_account_id(&mut self, treasury_account_id: AccountId) {
+        require!(
+            self.owner_account_id == env::predecessor_account_id(),
+            "only owner may call this method"
+        );
+
+        self.treasury.account_id = treasury_account_id.clone();
+        LotteryEvent::TreasuryChange {
+            treasury_account_id,
+        }
+        .emit();
+    }
+
+    /// returns the fees that haven't been withdrawn yet
+    pub fn get_accrued_fees(&self) -> U128 {
+        U128(self.treasury.accrued_of(&None))
+    }
+
+    /// returns all the fees collected over time, the withdrawn ones included
+    pub fn get_total_accrued_fees(&self) -> U128 {
+        U128(self.treasury.total_accrued_of(&None))
+    }
+
+    /// withdraws `amount`, or all if it isn't provided, of the accrued fees to the treasury;
+    /// if the transfer fails, the fees get restored
+    ///
+    /// who may call this method
+    ///     * owner
+    ///     * treasury
+    #[payable]
+    pub fn withdraw_fees(&mut self, amount: Option<U128>) -> Promise {
+        assert_one_yocto();
+        self.pause_flags.require_not_paused(Some(Feature::Payouts));
+        let caller = env::predecessor_account_id();
+        require!(
+            caller == self.owner_account_id || caller == self.treasury.account_id,
+            "only owner or treasury may call this method"
+        );
+
+        let amount = self.treasury.take(&None, amount.map(|a| a.0));
+        let treasury_account_id = self.treasury.account_id.clone();
+        LotteryEvent::FeesWithdraw {
+            treasury_account_id: treasury_account_id.clone(),
+            amount: U128(amount),
+        }
+        .emit();
+
+        Promise::new(treasury_account_id).transfer(amount).then(
+            Self::ext(env::current_account_id())
+                .with_static_gas(GAS_FOR_WITHDRAW_CALLBACK)
+                .on_withdraw_fees(U128(amount)),
+        )
+    }
+
+    /// callback of `withdraw_fees`; if the transfer has failed, the fees get restored
+    #[private]
+    pub fn on_withdraw_fees(&mut self, amount: U128) -> bool {
+        if let PromiseResult::Successful(_) = env::promise_result(0) {
+            return true;
+        }
+
+        self.treasury.restore(&None, amount.0);
+        LotteryEvent::FeesWithdrawFail { amount }.emit();
+        false
+    }
+
+    /// returns the funds an account may withdraw
+    pub fn get_claimable_balance(&self, account_id: AccountId) -> U128 {
+        U128(self.claims.balance_of(&account_id, &None))
     }
 
     /// returns the current balance of a LotteryItem
-    pub fn get_current_balance(&self, lottery_id: LotteryId) -> Balance {
-        let item = self.items.get(&lottery_id).unwrap();
-        item.current_balance
+    pub fn get_current_balance(&self, lottery_id: LotteryId) -> U128 {
+        let item = self.read_item(&lottery_id);
+        U128(item.current_balance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, RuntimeFeesConfig, VMConfig};
+
+    /// covers the storage of a lottery or a participant
+    const STORAGE_DEPOSIT: Balance = 10u128.pow(23);
+
+    fn get_context() -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .current_account_id("0.near".parse().unwrap())
+            .signer_account_id(accounts(0))
+            .predecessor_account_id(accounts(0));
+
+        builder
+    }
+
+    fn setup_contract() -> Lottery {
+        testing_env!(get_context().build());
+        Lottery::init(None, Some(1_000), None)
+    }
+
+    /// creates a lottery of 1000 organised by accounts(0), with accounts(1) as the only participant,
+    /// and picks the winner
+    fn create_lottery(contract: &mut Lottery) -> LotteryId {
+        testing_env!(get_context()
+            .attached_deposit(1000 + STORAGE_DEPOSIT)
+            .build());
+        let lottery_id = contract.new(None, accounts(0), U128(1000));
+
+        testing_env!(get_context().attached_deposit(STORAGE_DEPOSIT).build());
+        contract.add_participant(lottery_id.clone(), accounts(1));
+        assert_eq!(contract.pick_random_winner(lottery_id.clone()), accounts(1));
+        lottery_id
+    }
+
+    /// sets the result of the promise a callback gets called back with
+    fn set_promise_result(predecessor_account_id: &str, result: PromiseResult) {
+        testing_env!(
+            get_context()
+                .predecessor_account_id(predecessor_account_id.parse().unwrap())
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![result],
+        );
+    }
+
+    #[test]
+    fn test_release_prize_to_winner() {
+        let mut contract = setup_contract();
+        let lottery_id = create_lottery(&mut contract);
+        assert_eq!(
+            contract.release_prize_to_winner(lottery_id.clone()),
+            (U128(900), U128(100))
+        );
+
+        //the prize gets credited to the winner and the fee kept in the treasury
+        assert_eq!(contract.get_claimable_balance(accounts(1)).0, 900);
+        assert_eq!(contract.get_accrued_fees().0, 100);
+        assert_eq!(contract.get_current_balance(lottery_id).0, 0);
+        assert_eq!(contract.get_volume(accounts(0)).0, 1000);
+    }
+
+    #[test]
+    #[should_panic(expected = "no winner has been picked yet for lottery_id '1'")]
+    fn test_release_prize_before_picking_winner() {
+        let mut contract = setup_contract();
+        testing_env!(get_context()
+            .attached_deposit(1000 + STORAGE_DEPOSIT)
+            .build());
+        let lottery_id = contract.new(None, accounts(0), U128(1000));
+        contract.release_prize_to_winner(lottery_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "must be equal to or greater than the prize")]
+    fn test_release_prize_twice() {
+        let mut contract = setup_contract();
+        let lottery_id = create_lottery(&mut contract);
+        contract.release_prize_to_winner(lottery_id.clone());
+        contract.release_prize_to_winner(lottery_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "winner had already been chosen before")]
+    fn test_pick_winner_twice() {
+        let mut contract = setup_contract();
+        let lottery_id = create_lottery(&mut contract);
+        contract.pick_random_winner(lottery_id);
+    }
+
+    #[test]
+    fn test_fee_gets_clamped() {
+        let mut contract = setup_contract();
+        contract.set_fee_limits(U128(200), None);
+        let lottery_id = create_lottery(&mut contract);
+        assert_eq!(
+            contract.release_prize_to_winner(lottery_id),
+            (U128(800), U128(200))
+        );
+    }
+
+    #[test]
+    fn test_fee_override() {
+        let mut contract = setup_contract();
+        contract.set_fee_override(accounts(0), Some(0));
+        assert_eq!(contract.get_fee_percent_of(accounts(0)), 0);
+
+        let lottery_id = create_lottery(&mut contract);
+        assert_eq!(
+            contract.release_prize_to_winner(lottery_id),
+            (U128(1000), U128(0))
+        );
+        assert_eq!(contract.get_accrued_fees().0, 0);
+    }
+
+    #[test]
+    fn test_fee_tiers_by_volume() {
+        let mut contract = setup_contract();
+        contract.set_fee_tiers(vec![FeeTier {
+            min_volume: U128(1000),
+            fee_bps: 500,
+        }]);
+        assert_eq!(contract.get_fee_percent_of(accounts(0)), 1_000);
+
+        let lottery_id = create_lottery(&mut contract);
+        contract.release_prize_to_winner(lottery_id);
+        assert_eq!(contract.get_fee_percent_of(accounts(0)), 500);
+
+        //the next lottery gets the fee of the tier reached
+        testing_env!(get_context()
+            .attached_deposit(1000 + STORAGE_DEPOSIT)
+            .build());
+        let lottery_id = contract.new(None, accounts(0), U128(1000));
+        assert_eq!(
+            contract.get_lottery(lottery_id).get("fee_percent"),
+            Some(&"500".to_string())
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "only owner or fee_manager may call this method")]
+    fn test_set_fee_limits_without_role() {
+        let mut contract = setup_contract();
+        testing_env!(get_context().predecessor_account_id(accounts(1)).build());
+        contract.set_fee_limits(U128(200), None);
+    }
+
+    #[test]
+    fn test_withdraw_prize() {
+        let mut contract = setup_contract();
+        let lottery_id = create_lottery(&mut contract);
+        contract.release_prize_to_winner(lottery_id);
+
+        testing_env!(get_context()
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .build());
+        contract.withdraw(Some(U128(400)));
+        assert_eq!(contract.get_claimable_balance(accounts(1)).0, 500);
+
+        //the transfer has failed, so the funds get credited back
+        set_promise_result("0.near", PromiseResult::Failed);
+        assert!(!contract.on_withdraw(accounts(1), U128(400)));
+        assert_eq!(contract.get_claimable_balance(accounts(1)).0, 900);
+    }
+
+    #[test]
+    fn test_withdraw_fees_to_treasury() {
+        let mut contract = setup_contract();
+        contract.set_treasury_account_id(accounts(2));
+        assert_eq!(contract.get_treasury_account_id(), accounts(2));
+        let lottery_id = create_lottery(&mut contract);
+        contract.release_prize_to_winner(lottery_id);
+
+        testing_env!(get_context()
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(1)
+            .build());
+        contract.withdraw_fees(None);
+        assert_eq!(contract.get_accrued_fees().0, 0);
+        assert_eq!(contract.get_total_accrued_fees().0, 100);
+
+        //the transfer has failed, so the fees get restored
+        set_promise_result("0.near", PromiseResult::Failed);
+        assert!(!contract.on_withdraw_fees(U128(100)));
+        assert_eq!(contract.get_accrued_fees().0, 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "only owner or treasury may call this method")]
+    fn test_withdraw_fees_by_other_account() {
+        let mut contract = setup_contract();
+        testing_env!(get_context()
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .build());
+        contract.withdraw_fees(None);
+    }
+
+    #[test]
+    fn test_transfer_ownership() {
+        let mut contract = setup_contract();
+        contract.propose_owner(accounts(1));
+        assert_eq!(contract.get_proposed_owner_id(), Some(accounts(1)));
+
+        testing_env!(get_context().predecessor_account_id(accounts(1)).build());
+        contract.accept_ownership();
+        assert_eq!(contract.get_owner_id(), accounts(1));
+        assert_eq!(contract.get_proposed_owner_id(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "only the proposed owner may call this method")]
+    fn test_accept_ownership_by_other_account() {
+        let mut contract = setup_contract();
+        contract.propose_owner(accounts(1));
+
+        testing_env!(get_context().predecessor_account_id(accounts(2)).build());
+        contract.accept_ownership();
+    }
+
+    #[test]
+    fn test_operator_manages_participants() {
+        let mut contract = setup_contract();
+        let lottery_id = create_lottery(&mut contract);
+        assert!(contract.grant_role(accounts(2), Role::Operator));
+        assert!(contract.has_role(accounts(2), Role::Operator));
+
+        testing_env!(get_context()
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(STORAGE_DEPOSIT)
+            .build());
+        assert_eq!(
+            contract.add_participant(lottery_id.clone(), accounts(3)),
+            Some(accounts(3))
+        );
+        contract.opt_out_participant(lottery_id.clone(), accounts(3));
+        assert_eq!(
+            contract
+                .get_participant(lottery_id, accounts(3))
+                .unwrap()
+                .status,
+            ParticipantStatus::OptedOut
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "only organiser or operator of this lottery may call this method")]
+    fn test_add_participant_without_role() {
+        let mut contract = setup_contract();
+        let lottery_id = create_lottery(&mut contract);
+        testing_env!(get_context()
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(STORAGE_DEPOSIT)
+            .build());
+        contract.add_participant(lottery_id, accounts(3));
+    }
+
+    #[test]
+    #[should_panic(expected = "new_deals are paused")]
+    fn test_new_lottery_while_paused() {
+        let mut contract = setup_contract();
+        contract.grant_role(accounts(3), Role::Pauser);
+        testing_env!(get_context().predecessor_account_id(accounts(3)).build());
+        assert!(contract.pause(Some(Feature::NewDeals)));
+        assert!(contract.is_paused(Some(Feature::NewDeals)));
+        create_lottery(&mut contract);
+    }
+
+    #[test]
+    #[should_panic(expected = "payouts are paused")]
+    fn test_withdraw_while_payouts_paused() {
+        let mut contract = setup_contract();
+        let lottery_id = create_lottery(&mut contract);
+        contract.release_prize_to_winner(lottery_id);
+        contract.pause(Some(Feature::Payouts));
+
+        testing_env!(get_context()
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .build());
+        contract.withdraw(None);
+    }
+
+    #[test]
+    #[should_panic(expected = "must cover the amount (1000) plus the storage cost")]
+    fn test_storage_not_covered() {
+        let mut contract = setup_contract();
+        testing_env!(get_context().attached_deposit(1000).build());
+        contract.new(None, accounts(0), U128(1000));
+    }
+
+    #[test]
+    fn test_storage_deposit_pays_for_lotteries() {
+        let mut contract = setup_contract();
+        testing_env!(get_context().attached_deposit(STORAGE_DEPOSIT).build());
+        contract.storage_deposit(None, None);
+        let available = contract
+            .storage_balance_of(accounts(0))
+            .unwrap()
+            .available
+            .0;
+
+        //nothing but the prize is attached
+        testing_env!(get_context().attached_deposit(1000).build());
+        let lottery_id = contract.new(None, accounts(0), U128(1000));
+        assert_eq!(contract.get_current_balance(lottery_id.clone()).0, 1000);
+        let after_lottery = contract
+            .storage_balance_of(accounts(0))
+            .unwrap()
+            .available
+            .0;
+        assert!(after_lottery < available);
+
+        testing_env!(get_context().build());
+        contract.add_participant(lottery_id, accounts(1));
+        let balance = contract.storage_balance_of(accounts(0)).unwrap();
+        assert!(balance.available.0 < after_lottery);
+        assert_eq!(balance.total.0, STORAGE_DEPOSIT);
+    }
+
+    #[test]
+    fn test_remove_lottery_releases_storage() {
+        let mut contract = setup_contract();
+        testing_env!(get_context().attached_deposit(STORAGE_DEPOSIT).build());
+        contract.storage_deposit(None, None);
+        let available = contract
+            .storage_balance_of(accounts(0))
+            .unwrap()
+            .available
+            .0;
+
+        testing_env!(get_context().attached_deposit(1000).build());
+        let lottery_id = contract.new(Some("draw".to_string()), accounts(0), U128(1000));
+        testing_env!(get_context().build());
+        contract.add_participant(lottery_id.clone(), accounts(1));
+        contract.pick_random_winner(lottery_id.clone());
+        contract.release_prize_to_winner(lottery_id.clone());
+
+        contract.remove_lottery(lottery_id.clone());
+        assert!(contract.items.get(&lottery_id).is_none());
+        assert!(contract
+            .list_lotteries_by_external_ref("draw".to_string(), None, None)
+            .is_empty());
+        assert_eq!(
+            contract
+                .storage_balance_of(accounts(0))
+                .unwrap()
+                .available
+                .0,
+            available
+        );
+
+        testing_env!(get_context().attached_deposit(1).build());
+        assert!(contract.storage_unregister(None));
+    }
+
+    #[test]
+    #[should_panic(expected = "hasn't been released, therefore it may not be removed")]
+    fn test_remove_lottery_before_release() {
+        let mut contract = setup_contract();
+        let lottery_id = create_lottery(&mut contract);
+        contract.remove_lottery(lottery_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "only organiser or operator of this lottery may call this method")]
+    fn test_remove_lottery_by_other_account() {
+        let mut contract = setup_contract();
+        let lottery_id = create_lottery(&mut contract);
+        contract.release_prize_to_winner(lottery_id.clone());
+
+        testing_env!(get_context().predecessor_account_id(accounts(1)).build());
+        contract.remove_lottery(lottery_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "still take storage")]
+    fn test_storage_unregister_with_lotteries() {
+        let mut contract = setup_contract();
+        testing_env!(get_context().attached_deposit(STORAGE_DEPOSIT).build());
+        contract.storage_deposit(None, None);
+        testing_env!(get_context().attached_deposit(1000).build());
+        contract.new(None, accounts(0), U128(1000));
+
+        testing_env!(get_context().attached_deposit(1).build());
+        contract.storage_unregister(None);
+    }
+
+    #[test]
+    fn test_stage_and_deploy_code() {
+        let mut contract = setup_contract();
+        testing_env!(get_context().block_timestamp(100).build());
+        let staged_code = contract.stage_code(Base64VecU8(b"code".to_vec()));
+        assert_eq!(contract.get_staged_code(), Some(staged_code));
+        assert_eq!(staged_code.deployable_at, 100 + upgrade::MIN_DEPLOY_DELAY);
+
+        testing_env!(get_context()
+            .block_timestamp(staged_code.deployable_at)
+            .build());
+        contract.deploy_staged_code();
+        assert_eq!(contract.get_staged_code(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "may not be deployed until")]
+    fn test_deploy_code_before_delay() {
+        let mut contract = setup_contract();
+        contract.stage_code(Base64VecU8(b"code".to_vec()));
+        contract.deploy_staged_code();
     }
 }
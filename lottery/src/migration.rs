@@ -0,0 +1,343 @@
+use crate::*;
+use antioquia_common::versioned::{self, read_version, unknown_version, write_version};
+use std::io::{self, Write};
+
+/// the version of the layout of the contract state; stored under a key of its own,
+/// which `migrate` reads, see `versioned::write_state_version`
+pub const STATE_VERSION: u32 = 1;
+
+/// a LotteryItem as it was deployed first, unversioned; `current_fee_percent` is in percent
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct LotteryItemV0 {
+    pub lottery_id: LotteryId,
+    pub status: Status,
+    pub organiser_account_id: AccountId,
+    pub participants: BTreeMap<AccountId, Participant>,
+    pub winner: Option<AccountId>,
+    pub agreed_prize_amount: Balance,
+    pub current_balance: Balance,
+    pub prize_status: PrizeStatus,
+    pub current_fee_percent: u128,
+}
+
+/// a lottery as stored, in any of its layouts; it gets upgraded to LotteryItem once read
+/// and stored as such once written back
+pub enum VersionedLotteryItem {
+    V0(LotteryItemV0),
+    V1(LotteryItem),
+}
+
+impl BorshSerialize for VersionedLotteryItem {
+    fn serialize<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        match self {
+            Self::V0(item) => {
+                write_version(0, writer)?;
+                item.serialize(writer)
+            }
+            Self::V1(item) => {
+                write_version(1, writer)?;
+                item.serialize(writer)
+            }
+        }
+    }
+}
+
+impl BorshDeserialize for VersionedLotteryItem {
+    fn deserialize(buf: &mut &[u8]) -> io::Result<Self> {
+        match read_version(buf)? {
+            0 => Ok(Self::V0(LotteryItemV0::deserialize(buf)?)),
+            1 => Ok(Self::V1(LotteryItem::deserialize(buf)?)),
+            version => Err(unknown_version(version)),
+        }
+    }
+}
+
+impl From<LotteryItem> for VersionedLotteryItem {
+    fn from(item: LotteryItem) -> Self {
+        Self::V1(item)
+    }
+}
+
+impl From<LotteryItemV0> for LotteryItem {
+    fn from(item: LotteryItemV0) -> Self {
+        Self {
+            //the lotteries created before had the organiser choose their lottery_id, which serves as their reference
+            external_ref: Some(item.lottery_id.clone()),
+            lottery_id: item.lottery_id,
+            status: item.status,
+            organiser_account_id: item.organiser_account_id,
+            participants: item.participants,
+            winner: item.winner,
+            agreed_prize_amount: item.agreed_prize_amount,
+            current_balance: item.current_balance,
+            prize_status: item.prize_status,
+            current_fee_percent: item.current_fee_percent * fees::BPS_PER_PERCENT,
+            //unknown for the lotteries created before
+            started_at: None,
+            ended_at: None,
+            //the contract has paid for the storage
+            storage_costs: BTreeMap::new(),
+        }
+    }
+}
+
+impl From<VersionedLotteryItem> for LotteryItem {
+    fn from(item: VersionedLotteryItem) -> Self {
+        match item {
+            VersionedLotteryItem::V0(item) => item.into(),
+            VersionedLotteryItem::V1(item) => item,
+        }
+    }
+}
+
+/// the contract state as it was deployed first; `base_fee_percent` is in percent
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct LotteryV0 {
+    pub owner_account_id: AccountId,
+    pub base_fee_percent: u128,
+    //the layout of a collection doesn't depend on the type of its values
+    pub items: TreeMap<LotteryId, VersionedLotteryItem>,
+}
+
+/// the contract state in any of its layouts
+#[allow(clippy::large_enum_variant)]
+pub enum VersionedLottery {
+    V0(LotteryV0),
+    V1(Lottery),
+}
+
+impl VersionedLottery {
+    /// reads the state in the layout of its version, see `versioned::read_state_version`
+    pub fn read() -> Self {
+        let state = versioned::read_state();
+        let result = match versioned::read_state_version() {
+            0 => LotteryV0::try_from_slice(&state).map(Self::V0),
+            1 => Lottery::try_from_slice(&state).map(Self::V1),
+            version => env::panic_str(&format!("the state is of an unknown version {}", version)),
+        };
+        result.unwrap_or_else(|_| env::panic_str("the state doesn't match its version"))
+    }
+
+    pub fn version(&self) -> u32 {
+        match self {
+            Self::V0(_) => 0,
+            Self::V1(_) => STATE_VERSION,
+        }
+    }
+
+    pub fn owner_account_id(&self) -> &AccountId {
+        match self {
+            Self::V0(lottery) => &lottery.owner_account_id,
+            Self::V1(lottery) => &lottery.owner_account_id,
+        }
+    }
+}
+
+impl From<LotteryV0> for Lottery {
+    fn from(lottery: LotteryV0) -> Self {
+        Self {
+            treasury: Treasury::new(lottery.owner_account_id.clone(), b"a", b"r"),
+            owner_account_id: lottery.owner_account_id,
+            proposed_owner_id: None,
+            roles: Roles::new(b"g"),
+            pause_flags: PauseFlags::default(),
+            base_fee_percent: lottery.base_fee_percent * fees::BPS_PER_PERCENT,
+            fee_limits: FeeLimits::default(),
+            fee_schedule: FeeSchedule::new(b"o", b"v"),
+            items: lottery.items,
+            claims: ClaimLedger::new(b"c"),
+            storage_accounts: StorageAccounts::new(b"m"),
+            //the lottery_ids taken by then get skipped, see Lottery::next_lottery_id
+            last_lottery_id: 0,
+            //the lotteries created before get indexed page by page, see Lottery::index_old_lotteries
+            items_by_external_ref: LookupMap::new(b"x"),
+            index_backfill: Backfill::Pending { after: None },
+        }
+    }
+}
+
+impl From<VersionedLottery> for Lottery {
+    fn from(state: VersionedLottery) -> Self {
+        match state {
+            VersionedLottery::V0(lottery) => lottery.into(),
+            VersionedLottery::V1(lottery) => lottery,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    fn lottery_item_v0(lottery_id: &str) -> LotteryItemV0 {
+        let mut participants = BTreeMap::new();
+        participants.insert(
+            accounts(1),
+            Participant {
+                status: ParticipantStatus::Active,
+            },
+        );
+
+        LotteryItemV0 {
+            lottery_id: lottery_id.to_string(),
+            status: Status::New,
+            organiser_account_id: accounts(0),
+            participants,
+            winner: Some(accounts(1)),
+            agreed_prize_amount: 1000,
+            current_balance: 1000,
+            prize_status: PrizeStatus::DepositFunded,
+            //percent
+            current_fee_percent: 10,
+        }
+    }
+
+    /// writes a state of the layout deployed first with a single lottery, of `lottery_id` chosen by its organiser
+    fn write_state_v0(lottery_id: &str) {
+        let mut items: TreeMap<LotteryId, LotteryItemV0> = TreeMap::new(b"t");
+        items.insert(&lottery_id.to_string(), &lottery_item_v0(lottery_id));
+
+        let state = LotteryV0 {
+            owner_account_id: accounts(0),
+            //percent
+            base_fee_percent: 5,
+            items: TreeMap::try_from_slice(&items.try_to_vec().unwrap()).unwrap(),
+        };
+        env::state_write(&state);
+    }
+
+    #[test]
+    fn test_read_lottery_item_of_baseline_layout() {
+        //written before the lotteries got versioned
+        let bytes = lottery_item_v0("l0").try_to_vec().unwrap();
+        let item = LotteryItem::from(VersionedLotteryItem::try_from_slice(&bytes).unwrap());
+        assert_eq!(item.lottery_id, "l0");
+        assert_eq!(item.organiser_account_id, accounts(0));
+        assert_eq!(item.participants.len(), 1);
+        assert_eq!(item.prize_status, PrizeStatus::DepositFunded);
+        assert_eq!(item.current_fee_percent, 1_000);
+        assert_eq!(item.started_at, None);
+        assert_eq!(item.external_ref, Some("l0".to_string()));
+
+        //written back in the latest layout
+        let bytes = VersionedLotteryItem::from(item).try_to_vec().unwrap();
+        assert!(matches!(
+            VersionedLotteryItem::try_from_slice(&bytes).unwrap(),
+            VersionedLotteryItem::V1(_)
+        ));
+    }
+
+    #[test]
+    fn test_migrate_state_of_baseline_layout() {
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(0))
+            .build());
+        write_state_v0("l0");
+
+        let mut contract = Lottery::migrate();
+        assert_eq!(contract.get_state_version(), STATE_VERSION);
+        assert_eq!(versioned::read_state_version(), STATE_VERSION);
+        assert_eq!(contract.get_owner_id(), accounts(0));
+        assert_eq!(contract.get_fee_percent_of(accounts(3)), 500);
+        assert_eq!(contract.get_treasury_account_id(), accounts(0));
+
+        //the lottery_id chosen by the organiser serves as the reference, once indexed
+        assert!(contract
+            .list_lotteries_by_external_ref("l0".to_string(), None, None)
+            .is_empty());
+        assert!(!contract.index_old_lotteries(Some(1)));
+        assert!(contract.index_old_lotteries(Some(1)));
+        assert_eq!(
+            contract.list_lotteries_by_external_ref("l0".to_string(), None, None),
+            vec!["l0".to_string()]
+        );
+
+        //the lottery keeps its fee, in basis points now
+        assert_eq!(
+            contract.release_prize_to_winner("l0".to_string()),
+            (U128(900), U128(100))
+        );
+        assert_eq!(contract.get_claimable_balance(accounts(1)).0, 900);
+    }
+
+    #[test]
+    fn test_old_lottery_updated_before_backfill_gets_indexed() {
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(0))
+            .build());
+        write_state_v0("l0");
+
+        //written back in the latest layout, and indexed along
+        let mut contract = Lottery::migrate();
+        contract.release_prize_to_winner("l0".to_string());
+        assert_eq!(
+            contract.list_lotteries_by_external_ref("l0".to_string(), None, None),
+            vec!["l0".to_string()]
+        );
+
+        //the backfill skips it
+        assert!(contract.index_old_lotteries(None));
+        assert_eq!(
+            contract.list_lotteries_by_external_ref("l0".to_string(), None, None),
+            vec!["l0".to_string()]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "only owner may call this method")]
+    fn test_index_old_lotteries_by_other_account() {
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(0))
+            .build());
+        write_state_v0("l0");
+        let mut contract = Lottery::migrate();
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.index_old_lotteries(None);
+    }
+
+    #[test]
+    fn test_generated_ids_skip_those_of_old_layout() {
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        write_state_v0("1");
+
+        let mut contract = Lottery::migrate();
+        let lottery_id = contract.new(Some("draw".to_string()), accounts(0), U128(1000));
+        assert_eq!(lottery_id, "2");
+        assert_eq!(
+            contract.list_lotteries_by_external_ref("draw".to_string(), None, None),
+            vec!["2".to_string()]
+        );
+        assert_eq!(contract.get_current_balance("1".to_string()).0, 1000);
+    }
+
+    #[test]
+    fn test_migrate_state_of_latest_layout() {
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(0))
+            .build());
+        let contract = Lottery::init(Some(accounts(0)), Some(1_000), None);
+        env::state_write(&contract);
+
+        let contract = Lottery::migrate();
+        assert_eq!(contract.get_state_version(), STATE_VERSION);
+        assert_eq!(contract.get_fee_percent_of(accounts(3)), 1_000);
+    }
+
+    #[test]
+    #[should_panic(expected = "only owner may call this method")]
+    fn test_migrate_by_other_account() {
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(1))
+            .build());
+        write_state_v0("l0");
+        Lottery::migrate();
+    }
+}
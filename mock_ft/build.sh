@@ -0,0 +1,6 @@
+#!/bin/sh
+
+echo ">> Building contract"
+
+rustup target add wasm32-unknown-unknown
+cargo build --all --target wasm32-unknown-unknown --release
\ No newline at end of file
@@ -0,0 +1,6 @@
+#!/bin/sh
+
+./build.sh
+
+echo ">> Deploying contract 'antioquia_mock_ft'"
+near dev-deploy --wasmFile ./target/wasm32-unknown-unknown/release/antioquia_mock_ft.wasm
\ No newline at end of file
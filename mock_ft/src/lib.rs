@@ -0,0 +1,51 @@
+use near_contract_standards::fungible_token::metadata::{FungibleTokenMetadata, FungibleTokenMetadataProvider};
+use near_contract_standards::fungible_token::FungibleToken;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::{env, near_bindgen, AccountId, Balance, PanicOnDefault, PromiseOrValue};
+
+/// a NEP-141 token for testing the escrow locally or on testnet;
+/// anyone may mint any amount of it
+#[near_bindgen]
+#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
+pub struct MockFt {
+    pub token: FungibleToken,
+}
+
+near_contract_standards::impl_fungible_token_core!(MockFt, token);
+near_contract_standards::impl_fungible_token_storage!(MockFt, token);
+
+#[near_bindgen]
+impl MockFt {
+    #[init]
+    pub fn init() -> Self {
+        Self {
+            token: FungibleToken::new(b"t"),
+        }
+    }
+
+    /// registers `account_id`, if needed, and mints `amount` to it
+    pub fn mint(&mut self, account_id: AccountId, amount: U128) {
+        if !self.token.accounts.contains_key(&account_id) {
+            self.token.internal_register_account(&account_id);
+        }
+
+        let amount: Balance = amount.into();
+        self.token.internal_deposit(&account_id, amount);
+    }
+}
+
+#[near_bindgen]
+impl FungibleTokenMetadataProvider for MockFt {
+    fn ft_metadata(&self) -> FungibleTokenMetadata {
+        FungibleTokenMetadata {
+            spec: "ft-1.0.0".to_string(),
+            name: "Mock FT".to_string(),
+            symbol: format!("MOCK-{}", env::current_account_id()),
+            icon: None,
+            reference: None,
+            reference_hash: None,
+            decimals: 6,
+        }
+    }
+}